@@ -1,114 +1,431 @@
-use crate::tokenizer::Token;
-use std::slice::Iter;
-use std::iter::Peekable;
-use std::collections::HashMap;
+use crate::tokenizer::{Span, Token, TokenStream};
+use std::cmp::Ordering;
+use regex::Regex;
+use crate::value::{Type, Value};
+use crate::functions::{self, Function};
+use crate::table::Row;
+use crate::error::RelaxError;
 
-#[derive (Debug)]
+#[derive (Debug, Clone)]
 pub enum Condition {
-    Binary { left: Box<Condition>, operator: Token, right: Box<Condition>},
-    Unary { operator: Token, right: Box<Condition> },
+    Binary { left: Box<Condition>, operator: Token, right: Box<Condition>, span: Span },
+    Unary { operator: Token, right: Box<Condition>, span: Span },
     Grouping(Box<Condition>),
-    Literal(Token),
+    Literal(Token, Span),
+    Call { function: &'static Function, arguments: Vec<Condition>, span: Span },
+    /// `text like pattern` or `text ~ pattern`. A literal pattern is compiled
+    /// once when it is parsed, other patterns for each row.
+    Pattern { text: Box<Condition>, operator: Token, pattern: Box<Condition>, compiled: Option<Regex>, span: Span },
+    /// `case when <condition> then <result> ... else <otherwise> end`
+    Case { branches: Vec<(Condition, Condition)>, otherwise: Option<Box<Condition>>, span: Span },
 }
 
 impl Condition {
-    pub fn eval<'a>(&'a self, row_lookup: &'a HashMap<String, String>) -> String {
+    pub fn span(&self) -> Span {
         match self {
-            Condition::Binary { left, operator, right } => {
-                match operator {
-                    Token::Equals => {(left.eval(row_lookup) == right.eval(row_lookup)).to_string()},
-                    Token::Greater => {(left.eval(row_lookup) > right.eval(row_lookup)).to_string()},
-                    Token::GreaterEq => {(left.eval(row_lookup) >= right.eval(row_lookup)).to_string()},
-                    Token::Lesser => {(left.eval(row_lookup) < right.eval(row_lookup)).to_string()},
-                    Token::LesserEq => {(left.eval(row_lookup) <= right.eval(row_lookup)).to_string()},
-                    Token::And => {(left.eval(row_lookup).parse().unwrap() && right.eval(row_lookup).parse().unwrap()).to_string()},
-                    Token::Or => {(left.eval(row_lookup).parse().unwrap() || right.eval(row_lookup).parse().unwrap()).to_string()},
-                    Token::Comma=> {
-                        let result = left.eval(row_lookup) + "," + &right.eval(row_lookup);
-                        result
+            Condition::Binary { span, .. } | Condition::Unary { span, .. } | Condition::Literal(_, span)
+                | Condition::Call { span, .. } | Condition::Pattern { span, .. } | Condition::Case { span, .. } => *span,
+            Condition::Grouping(condition) => condition.span(),
+        }
+    }
+
+    /// Whether this condition is just a name, possibly in parentheses.
+    pub fn is_name(&self) -> bool {
+        match self {
+            Condition::Literal(Token::Symbol(_), _) => true,
+            Condition::Grouping(condition) => condition.is_name(),
+            _ => false,
+        }
+    }
+
+    /// The type this condition evaluates to, if it can be told without
+    /// looking at a row.
+    pub fn static_type(&self) -> Option<Type> {
+        match self {
+            Condition::Literal(Token::Symbol(_), _) => None,
+            Condition::Literal(token, _) => Value::from_token(token).ok()?.ty(),
+            Condition::Grouping(condition) => condition.static_type(),
+            Condition::Call { function, .. } => function.returns,
+            Condition::Unary { operator: Token::Minus, right, .. } => right.static_type(),
+            Condition::Unary { .. } | Condition::Pattern { .. } => Some(Type::Boolean),
+            Condition::Binary { operator: Token::Plus | Token::Minus | Token::Multiply | Token::Divide, .. } => None,
+            Condition::Binary { .. } => Some(Type::Boolean),
+            Condition::Case { branches, otherwise, .. } => {
+                let results = branches.iter().map( |(_, result)| result).chain(otherwise.as_deref());
+                results.map( |result| result.static_type()).reduce( |left, right| common_type(left?, right?))?
+            },
+        }
+    }
+
+    pub fn eval(&self, row: &Row) -> Result<Value, RelaxError> {
+        self.eval_node(row).map_err( |error| error.at(self.span()))
+    }
+
+    fn eval_node(&self, row: &Row) -> Result<Value, RelaxError> {
+        match self {
+            Condition::Binary { left, operator, right, .. } => {
+                let left = left.eval(row)?;
+                let right = right.eval(row)?;
+                // Comparisons involving Null have no ordering and are unknown
+                let result = match operator {
+                    Token::Equals => left.compare(&right)?.map( |ordering| ordering == Ordering::Equal),
+                    Token::NotEquals => left.compare(&right)?.map( |ordering| ordering != Ordering::Equal),
+                    Token::Greater => left.compare(&right)?.map( |ordering| ordering == Ordering::Greater),
+                    Token::GreaterEq => left.compare(&right)?.map( |ordering| ordering != Ordering::Less),
+                    Token::Lesser => left.compare(&right)?.map( |ordering| ordering == Ordering::Less),
+                    Token::LesserEq => left.compare(&right)?.map( |ordering| ordering != Ordering::Greater),
+                    Token::And => match (as_bool(&left)?, as_bool(&right)?) {
+                        (Some(false), _) | (_, Some(false)) => Some(false),
+                        (Some(true), Some(true)) => Some(true),
+                        _ => None,
                     },
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Or => match (as_bool(&left)?, as_bool(&right)?) {
+                        (Some(true), _) | (_, Some(true)) => Some(true),
+                        (Some(false), Some(false)) => Some(false),
+                        _ => None,
+                    },
+                    Token::Plus => return left.add(&right),
+                    Token::Minus => return left.sub(&right),
+                    Token::Multiply => return left.mul(&right),
+                    Token::Divide => return left.div(&right),
+                    _ => return Err(RelaxError::Parse(format!("can't evaluate {operator:?} in a condition"))),
+                };
+                Ok(result.map_or(Value::Null, Value::Boolean))
+            },
+            Condition::Pattern { text, operator, pattern, compiled, .. } => {
+                let text = text.eval(row)?;
+                let pattern = pattern.eval(row)?;
+                match (&text, &pattern, compiled) {
+                    (Value::Null, _, _) | (_, Value::Null, _) => Ok(Value::Null),
+                    (Value::String(text), _, Some(regex)) => Ok(Value::Boolean(regex.is_match(text))),
+                    (Value::String(text), Value::String(pattern), None) => Ok(Value::Boolean(compile(operator, pattern)?.is_match(text))),
+                    _ => Err(RelaxError::Type(format!("cannot match {} value '{text}' against {} value '{pattern}'",
+                        text.ty().unwrap(), pattern.ty().unwrap()))),
                 }
             },
-            Condition::Unary{ operator, right } => {
+            Condition::Unary{ operator, right, .. } => {
+                let right = right.eval(row)?;
                 match operator {
-                    Token::Not => {(!right.eval(row_lookup).parse::<bool>().unwrap()).to_string()},
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Not => Ok(as_bool(&right)?.map_or(Value::Null, |value| Value::Boolean(!value))),
+                    Token::Minus => right.neg(),
+                    Token::Is => Ok(Value::Boolean(right == Value::Null)),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?} in a condition"))),
                 }
             },
-            Condition::Literal(token) => {
+            Condition::Literal(token, _) => {
                 match token {
-                    Token::Symbol(key) => row_lookup.get(key).unwrap().to_string(),
-                    Token::Number(val) | Token::String(val) => val.to_string(),
-                    _ => panic!("error: can't evaluate {token:?} Literal"),
+                    Token::Symbol(key) => row.get(key).cloned(),
+                    _ => Value::from_token(token),
+                }
+            },
+            Condition::Grouping(condition) => condition.eval(row),
+            Condition::Call { function, arguments, .. } => {
+                let arguments = arguments.iter()
+                    .map( |argument| argument.eval(row))
+                    .collect::<Result<Vec<Value>, RelaxError>>()?;
+                function.call(&arguments)
+            },
+            Condition::Case { branches, otherwise, .. } => {
+                // Like select, a branch whose condition is unknown isn't taken
+                for (condition, result) in branches {
+                    if as_bool(&condition.eval(row)?)? == Some(true) {
+                        return result.eval(row);
+                    }
                 }
+                otherwise.as_ref().map_or(Ok(Value::Null), |otherwise| otherwise.eval(row))
             },
-            Condition::Grouping(condition) => condition.eval(&row_lookup),
         }
     }
 }
 
-pub fn parse(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Condition> { 
-    let mut condition = comparison(tokens);
+/// The type that values of both `left` and `right` fit in, if any.
+fn common_type(left: Type, right: Type) -> Option<Type> {
+    match (left, right) {
+        (Type::Integer, Type::Decimal) | (Type::Decimal, Type::Integer) => Some(Type::Decimal),
+        (left, right) => (left == right).then_some(left),
+    }
+}
 
-    while let Some(token) = tokens.peek() {
-        match token {
-            Token::And | Token::Or => {
-                let operator = tokens.next().unwrap().clone();
-                let right = comparison(tokens);
-                condition = Box::new(Condition::Binary {left: condition, operator, right}); 
-            }
-            _ => break,
-        }
+/// Compiles a `like` pattern, where `%` is any run of characters and `_` is
+/// any one character, or a `~` regular expression, which can match anywhere
+/// in the text.
+fn compile(operator: &Token, pattern: &str) -> Result<Regex, RelaxError> {
+    let expression = match operator {
+        Token::Like => {
+            let pattern = pattern.chars().map( |c| match c {
+                '%' => ".*".to_string(),
+                '_' => ".".to_string(),
+                c => regex::escape(&c.to_string()),
+            }).collect::<String>();
+            format!("^(?s:{pattern})$")
+        },
+        _ => pattern.to_string(),
+    };
+    Regex::new(&expression)
+        .map_err( |error| {
+            // Regex syntax errors echo the pattern over several lines; keep just the reason
+            let reason = error.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ").to_string();
+            RelaxError::Parse(format!("invalid pattern '{pattern}': {reason}"))
+        })
+}
+
+/// A Boolean truth value, or `None` for unknown (`Null`).
+fn as_bool(value: &Value) -> Result<Option<bool>, RelaxError> {
+    match value {
+        Value::Boolean(value) => Ok(Some(*value)),
+        Value::Null => Ok(None),
+        _ => Err(RelaxError::Type(format!("expected Boolean but found '{value}'"))),
     }
+}
 
-    condition
+pub fn parse(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    let mut condition = conjunction(tokens)?;
+
+    while let Some(Token::Or) = tokens.peek() {
+        let operator = tokens.next().unwrap().clone();
+        let right = conjunction(tokens)?;
+        let span = condition.span().to(right.span());
+        condition = Box::new(Condition::Binary {left: condition, operator, right, span});
+    }
+
+    Ok(condition)
+}
+
+/// `and` binds tighter than `or`, so `a or b and c` is `a or (b and c)`.
+fn conjunction(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    let mut condition = negation(tokens)?;
+
+    while let Some(Token::And) = tokens.peek() {
+        let operator = tokens.next().unwrap().clone();
+        let right = negation(tokens)?;
+        let span = condition.span().to(right.span());
+        condition = Box::new(Condition::Binary {left: condition, operator, right, span});
+    }
+
+    Ok(condition)
 }
 
-fn comparison(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Condition> { 
-    let mut condition = unary(tokens);
+/// `not` applies to a whole comparison, so `not a = b` is `not (a = b)`.
+fn negation(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    if let Some(Token::Not) = tokens.peek() {
+        let operator = tokens.next().unwrap().clone();
+        let span = tokens.span();
+        let right = negation(tokens)?;
+        let span = span.to(right.span());
+        return Ok(Box::new(Condition::Unary {operator, right, span}))
+    }
+    comparison(tokens)
+}
+
+fn comparison(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    let mut condition = term(tokens)?;
 
     while let Some(token) = tokens.peek() {
         match token {
-            Token::Greater | Token::GreaterEq | Token::Lesser | Token::LesserEq | Token::Equals | Token::Comma => {
+            Token::Greater | Token::GreaterEq | Token::Lesser | Token::LesserEq | Token::Equals | Token::NotEquals => {
                 let operator = tokens.next().unwrap().clone();
-                let right = unary(tokens);
-                let new_cond = Condition::Binary { left: condition, operator, right};
+                let right = term(tokens)?;
+                let span = condition.span().to(right.span());
+                let new_cond = Condition::Binary { left: condition, operator, right, span };
                 condition = Box::new(new_cond);
             }
+            Token::Like | Token::Match | Token::In | Token::Between => condition = predicate(condition, tokens)?,
+            Token::Not => {
+                tokens.next();
+                if !matches!(tokens.peek(), Some(Token::Like | Token::Match | Token::In | Token::Between)) {
+                    return Err(RelaxError::Parse("expected like, ~, in or between after not".to_string()).at(tokens.peek_span()));
+                }
+                let right = predicate(condition, tokens)?;
+                let span = right.span();
+                condition = Box::new(Condition::Unary { operator: Token::Not, right, span });
+            }
+            Token::Is => {
+                // `x is null` is stored as `Is x`, and `x is not null` as `Not (Is x)`
+                tokens.next();
+                let negated = tokens.peek() == Some(&Token::Not);
+                if negated {
+                    tokens.next();
+                }
+                if tokens.next() != Some(&Token::Null) {
+                    return Err(RelaxError::Parse("expected null after is".to_string()).at(tokens.span()));
+                }
+                let span = condition.span().to(tokens.span());
+                condition = Box::new(Condition::Unary { operator: Token::Is, right: condition, span });
+                if negated {
+                    condition = Box::new(Condition::Unary { operator: Token::Not, right: condition, span });
+                }
+            }
             _ => break,
         }
     }
 
-    condition
+    Ok(condition)
 }
 
-fn unary(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Condition> { 
-    if let Some(token) = tokens.peek() {
-        match token {
-            Token::Minus | Token::Comma | Token::Not => {
-                let operator = tokens.next().unwrap().clone();
-                let right = unary(tokens);
-                return Box::new(Condition::Unary {operator, right})
-            },
-            _ => {},
+/// Parses the predicate following `left`. `between` and `in` are stored as
+/// the equivalent comparisons, so `x between 1 and 5` is `x >= 1 and x <= 5`
+/// and `x in (1, 2)` is `x = 1 or x = 2`.
+fn predicate(left: Box<Condition>, tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    match tokens.next() {
+        Some(operator @ (Token::Like | Token::Match)) => {
+            let pattern = term(tokens)?;
+            let span = left.span().to(pattern.span());
+            let compiled = match pattern.as_ref() {
+                Condition::Literal(Token::String(text), span) => Some(compile(operator, text).map_err( |error| error.at(*span))?),
+                _ => None,
+            };
+            Ok(Box::new(Condition::Pattern { text: left, operator: operator.clone(), pattern, compiled, span }))
+        },
+        Some(Token::Between) => {
+            let low = term(tokens)?;
+            if tokens.next() != Some(&Token::And) {
+                return Err(RelaxError::Parse("expected and between the bounds of between".to_string()).at(tokens.span()));
+            }
+            let high = term(tokens)?;
+            let span = left.span().to(high.span());
+            let lower = Box::new(Condition::Binary { left: left.clone(), operator: Token::GreaterEq, right: low, span });
+            let upper = Box::new(Condition::Binary { left, operator: Token::LesserEq, right: high, span });
+            Ok(Box::new(Condition::Binary { left: lower, operator: Token::And, right: upper, span }))
+        },
+        Some(Token::In) => {
+            let items = arguments(tokens, "in")?;
+            let span = left.span().to(tokens.span());
+            items.into_iter()
+                .map( |item| Box::new(Condition::Binary { left: left.clone(), operator: Token::Equals, right: Box::new(item), span }))
+                .reduce( |result, equal| Box::new(Condition::Binary { left: result, operator: Token::Or, right: equal, span }))
+                .ok_or_else( || RelaxError::Parse("in needs at least one value".to_string()).at(span))
+        },
+        _ => Err(RelaxError::Parse("expected like, ~, in or between".to_string()).at(tokens.span())),
+    }
+}
+
+/// Parses a parenthesized, comma separated list of conditions, such as the
+/// arguments to a function.
+fn arguments(tokens: &mut TokenStream, context: &str) -> Result<Vec<Condition>, RelaxError> {
+    if tokens.next() != Some(&Token::OpenParen) {
+        return Err(RelaxError::Parse(format!("expected '(' after {context}")).at(tokens.span()));
+    }
+    let mut arguments = vec![];
+    if tokens.peek() != Some(&Token::CloseParen) {
+        arguments.push(*parse(tokens)?);
+        while tokens.peek() == Some(&Token::Comma) {
+            tokens.next();
+            arguments.push(*parse(tokens)?);
         }
     }
+    if tokens.next() != Some(&Token::CloseParen) {
+        return Err(RelaxError::Parse(format!("expected ')' to close {context}")).at(tokens.span()));
+    }
+    Ok(arguments)
+}
+
+fn term(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    let mut condition = factor(tokens)?;
+
+    while let Some(Token::Plus | Token::Minus) = tokens.peek() {
+        let operator = tokens.next().unwrap().clone();
+        let right = factor(tokens)?;
+        let span = condition.span().to(right.span());
+        condition = Box::new(Condition::Binary { left: condition, operator, right, span });
+    }
+
+    Ok(condition)
+}
+
+fn factor(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    let mut condition = unary(tokens)?;
+
+    while let Some(Token::Multiply | Token::Divide) = tokens.peek() {
+        let operator = tokens.next().unwrap().clone();
+        let right = unary(tokens)?;
+        let span = condition.span().to(right.span());
+        condition = Box::new(Condition::Binary { left: condition, operator, right, span });
+    }
+
+    Ok(condition)
+}
+
+fn unary(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    if let Some(Token::Minus) = tokens.peek() {
+        let operator = tokens.next().unwrap().clone();
+        let span = tokens.span();
+        let right = unary(tokens)?;
+        let span = span.to(right.span());
+        return Ok(Box::new(Condition::Unary {operator, right, span}))
+    }
     primary(tokens)
 }
 
-fn primary(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Condition> { 
-    if let Some(token) = tokens.next() {
-        match token {
-            Token::Number(_) | Token::Symbol(_) | Token::String(_) => Box::new(Condition::Literal(token.clone())),
-            Token::OpenParen => { 
-                let expr = parse(tokens);
-                if tokens.next().unwrap() != &Token::CloseParen {panic!("error: expected ')' after expression")};
-                Box::new(Condition::Grouping(expr))
-            },
-            token => panic!("error: unable to parse {:?}", token),
+fn primary(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    match tokens.next() {
+        // A name followed by '(' is only a call if it names a function, so
+        // `select a = b (R)` still reads `(R)` as the relation
+        Some(Token::Symbol(name)) if tokens.peek() == Some(&Token::OpenParen) && functions::lookup(name).is_some() => {
+            let start = tokens.span();
+            let function = functions::lookup(name).unwrap();
+            let arguments = arguments(tokens, name)?;
+            let span = start.to(tokens.span());
+            function.check(&arguments, span)?;
+            Ok(Box::new(Condition::Call { function, arguments, span }))
+        },
+        Some(Token::Case) => case(tokens),
+        Some(token @ (Token::Number(_) | Token::Symbol(_) | Token::String(_) | Token::Boolean(_) | Token::Null)) => Ok(Box::new(Condition::Literal(token.clone(), tokens.span()))),
+        Some(Token::OpenParen) => {
+            let expr = parse(tokens)?;
+            if tokens.next() != Some(&Token::CloseParen) {
+                return Err(RelaxError::Parse("expected ')' after condition".to_string()).at(tokens.span()));
+            }
+            Ok(Box::new(Condition::Grouping(expr)))
+        },
+        Some(Token::Eof) | None => Err(RelaxError::Parse("unexpected end of condition".to_string()).at(tokens.span())),
+        Some(token) => Err(RelaxError::Parse(format!("unable to parse {token:?} in condition")).at(tokens.span())),
+    }
+}
+
+/// Parses the rest of a `case` expression after the `case` keyword. Every
+/// branch must produce the same type of value, as far as it is known here.
+fn case(tokens: &mut TokenStream) -> Result<Box<Condition>, RelaxError> {
+    let start = tokens.span();
+    let mut branches = vec![];
+    let mut result_type: Option<Type> = None;
+    let mut check_result = |result: &Condition| {
+        let Some(ty) = result.static_type() else { return Ok(()) };
+        result_type = match result_type {
+            None => Some(ty),
+            Some(expected) => Some(common_type(expected, ty).ok_or_else( ||
+                RelaxError::Type(format!("case results must all have the same type, found {expected} and {ty}")).at(result.span()))?),
+        };
+        Ok(())
+    };
+
+    while tokens.peek() == Some(&Token::When) {
+        tokens.next();
+        let condition = parse(tokens)?;
+        if let Some(ty) = condition.static_type().filter( |ty| *ty != Type::Boolean) {
+            return Err(RelaxError::Type(format!("case conditions must be Boolean, found {ty}")).at(condition.span()));
         }
+        if tokens.next() != Some(&Token::Then) {
+            return Err(RelaxError::Parse("expected then after case condition".to_string()).at(tokens.span()));
+        }
+        let result = parse(tokens)?;
+        check_result(&result)?;
+        branches.push((*condition, *result));
+    }
+    if branches.is_empty() {
+        return Err(RelaxError::Parse("expected when after case".to_string()).at(tokens.peek_span()));
+    }
+
+    let otherwise = if tokens.peek() == Some(&Token::Else) {
+        tokens.next();
+        let otherwise = parse(tokens)?;
+        check_result(&otherwise)?;
+        Some(otherwise)
     } else {
-        panic!("error: no more tokens");
+        None
+    };
+    if tokens.next() != Some(&Token::End) {
+        return Err(RelaxError::Parse("expected end to close case".to_string()).at(tokens.span()));
     }
+
+    Ok(Box::new(Condition::Case { branches, otherwise, span: start.to(tokens.span()) }))
 }
@@ -0,0 +1,226 @@
+use std::fs;
+use itertools::Itertools;
+use crate::table::Table;
+use crate::value::{Type, Value};
+use crate::error::RelaxError;
+
+/// A field of a CSV record, and whether it was quoted.
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+/// The fields of one CSV record and the line it starts on. Empty unquoted
+/// fields are `None`.
+type Record = (usize, Vec<Option<Field>>);
+
+/// Reads a table from the CSV file at `path`. The first record holds the
+/// headers, and each column takes the narrowest type all of its values
+/// parse as. A quoted value makes its column a String column, so text like
+/// `"02139"` keeps its leading zero. Empty fields are `Null`.
+pub fn load(path: &str) -> Result<Table, RelaxError> {
+    let text = fs::read_to_string(path)
+        .map_err( |error| RelaxError::Io(format!("unable to read '{path}': {error}")))?;
+    let mut records = parse(&text).map_err( |error| RelaxError::Parse(format!("{path}: {error}")))?.into_iter();
+
+    let Some((_, headers)) = records.next() else {
+        return Err(RelaxError::Schema(format!("{path}: expected a header row")));
+    };
+    let headers = headers.into_iter()
+        .map( |header| header.map( |header| header.text.trim().to_string()).filter( |header| !header.is_empty()))
+        .collect::<Option<Vec<String>>>()
+        .ok_or_else( || RelaxError::Schema(format!("{path}: every column needs a header")))?;
+    if let Some(header) = headers.iter().duplicates().next() {
+        return Err(RelaxError::Schema(format!("{path}: column '{header}' appears more than once")));
+    }
+
+    let mut fields = vec![];
+    for (line, record) in records {
+        if record.len() != headers.len() {
+            return Err(RelaxError::Schema(format!("{path}: line {line} has {} fields but there are {} headers", record.len(), headers.len())));
+        }
+        fields.push(record);
+    }
+
+    let types = (0..headers.len()).map( |i| {
+        let column: Vec<&Field> = fields.iter().filter_map( |record| record[i].as_ref()).collect();
+        if column.iter().any( |field| field.quoted) {
+            return Type::String;
+        }
+        [Type::Integer, Type::Decimal, Type::Boolean, Type::Date, Type::Timestamp].into_iter()
+            .find( |ty| !column.is_empty() && column.iter().all( |field| Value::parse(*ty, &field.text).is_ok()))
+            .unwrap_or(Type::String)
+    }).collect::<Vec<Type>>();
+
+    let mut table = Table::new(headers, types);
+    for record in fields {
+        let row = record.iter().zip(&table.types)
+            .map( |(field, ty)| field.as_ref().map_or(Ok(Value::Null), |field| Value::parse(*ty, &field.text)))
+            .collect::<Result<Vec<Value>, RelaxError>>()?;
+        table.rows.push(row);
+    }
+    Ok(table)
+}
+
+/// Writes `table` to the CSV file at `path`, with `Null` as an empty field.
+/// Strings are always quoted so they read back as Strings. The headers are
+/// written without their relations, so they have to be unique.
+pub fn save(table: &Table, path: &str) -> Result<(), RelaxError> {
+    if let Some(header) = table.headers.iter().duplicates().next() {
+        return Err(RelaxError::Schema(format!("column '{header}' appears more than once, rename it before saving")));
+    }
+    let mut text = String::new();
+    text.push_str(&table.headers.iter().map( |header| quote(header)).collect::<Vec<String>>().join(","));
+    text.push('\n');
+    for row in table.rows.iter() {
+        let fields = row.iter().map( |value| match value {
+            Value::Null => String::new(),
+            Value::String(text) => format!("\"{}\"", text.replace('"', "\"\"")),
+            value => value.to_string(),
+        });
+        text.push_str(&fields.collect::<Vec<String>>().join(","));
+        text.push('\n');
+    }
+    fs::write(path, text).map_err( |error| RelaxError::Io(format!("unable to write '{path}': {error}")))
+}
+
+/// Quotes `field` if it contains anything that would otherwise end it early.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits CSV text into records of fields, each paired with the line it
+/// starts on. Quoted fields can hold commas, newlines and `""` for a quote.
+/// Blank lines are skipped.
+fn parse(text: &str) -> Result<Vec<Record>, String> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.trim().is_empty() && !quoted => {
+                field.clear();
+                quoted = true;
+                let opened = line;
+                loop {
+                    match chars.next() {
+                        Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        },
+                        None => return Err(format!("line {opened}: unterminated quoted field")),
+                    }
+                }
+            },
+            ',' => record.push(take_field(&mut field, &mut quoted)),
+            '\r' if chars.peek() == Some(&'\n') => {},
+            '\n' => {
+                if !record.is_empty() || quoted || !field.trim().is_empty() {
+                    record.push(take_field(&mut field, &mut quoted));
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                field.clear();
+                line += 1;
+                start = line;
+            },
+            c if quoted => {
+                if !c.is_whitespace() {
+                    return Err(format!("line {line}: unexpected '{c}' after a quoted field"));
+                }
+            },
+            c => field.push(c),
+        }
+    }
+    if !record.is_empty() || quoted || !field.trim().is_empty() {
+        record.push(take_field(&mut field, &mut quoted));
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+fn take_field(field: &mut String, quoted: &mut bool) -> Option<Field> {
+    let text = std::mem::take(field);
+    if std::mem::take(quoted) {
+        Some(Field { text, quoted: true })
+    } else {
+        Some(text.trim().to_string()).filter( |text| !text.is_empty()).map( |text| Field { text, quoted: false })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn fields(text: &str) -> Vec<Vec<Option<String>>> {
+        parse(text).unwrap().into_iter()
+            .map( |(_, record)| record.into_iter().map( |field| field.map( |field| field.text)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn parses_quoted_fields() {
+        let text = "a, b\r\n\"x, y\",\"say \"\"hi\"\"\"\n\"two\nlines\",\n\n,\"\"\n";
+        assert_eq!(fields(text), vec![
+            vec![Some("a".to_string()), Some("b".to_string())],
+            vec![Some("x, y".to_string()), Some("say \"hi\"".to_string())],
+            vec![Some("two\nlines".to_string()), None],
+            vec![None, Some(String::new())],
+        ]);
+        assert_eq!(parse("a\n\"b\nc").map( |records| records.len()), Err("line 2: unterminated quoted field".to_string()));
+        assert!(parse("\"a\"b").is_err());
+    }
+
+    #[test]
+    fn save_then_load() {
+        let mut table = Table::new(
+            ["id", "name", "price", "paid", "on", "at", "zip"].map(String::from).to_vec(),
+            vec![Type::Integer, Type::String, Type::Decimal, Type::Boolean, Type::Date, Type::Timestamp, Type::String]);
+        table.rows.push(vec![Value::Integer(1), Value::String("a, \"b\"".to_string()), Value::Decimal("1.5".parse().unwrap()),
+            Value::Boolean(true), Value::Date("2024-02-29".parse().unwrap()), Value::Timestamp("2024-02-29 08:30:00".parse().unwrap()),
+            Value::String("02139".to_string())]);
+        table.rows.push(vec![Value::Integer(-2), Value::String(String::new()), Value::Null,
+            Value::Boolean(false), Value::Null, Value::Null, Value::String("two\nlines".to_string())]);
+
+        let path = env::temp_dir().join(format!("rust-relax-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        save(&table, path).unwrap();
+        let loaded = load(path);
+        fs::remove_file(path).unwrap();
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.headers, table.headers);
+        assert_eq!(loaded.types, table.types);
+        assert_eq!(loaded.rows, table.rows);
+    }
+
+    #[test]
+    fn load_rejects_duplicate_headers() {
+        let path = env::temp_dir().join(format!("rust-relax-duplicates-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, "a,b,a\n1,2,3\n").unwrap();
+        let loaded = load(path);
+        fs::remove_file(path).unwrap();
+        assert!(matches!(loaded, Err(RelaxError::Schema(_))));
+    }
+
+    #[test]
+    fn save_rejects_duplicate_headers() {
+        let table = Table::new(vec!["id".to_string(), "id".to_string()], vec![Type::Integer, Type::Integer]);
+        let path = env::temp_dir().join("rust-relax-duplicates.csv");
+        assert!(matches!(save(&table, path.to_str().unwrap()), Err(RelaxError::Schema(_))));
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt::{Display, Formatter, Error};
+use crate::tokenizer::Span;
+
+/// Every way a statement can fail, from reading characters to evaluating a query.
+#[derive (Debug, Clone, PartialEq)]
+pub enum RelaxError {
+    /// The input contains a character or literal that can't be tokenized.
+    Lex(String),
+    /// The tokens don't form a valid statement.
+    Parse(String),
+    /// Tables or rows have incompatible shapes, e.g. a union of tables with different columns.
+    Schema(String),
+    /// A value has the wrong type for the operation applied to it.
+    Type(String),
+    /// A calculation overflowed or divided by zero.
+    Arithmetic(String),
+    /// A file couldn't be read or written.
+    Io(String),
+    UnknownTable(String),
+    UnknownColumn(String),
+    /// An unqualified column name matches columns from more than one relation.
+    AmbiguousColumn(String),
+    /// An error while evaluating the named view.
+    InView(String, Box<RelaxError>),
+    /// Any of the above, attributed to the part of the input that caused it.
+    At(Box<RelaxError>, Span),
+}
+
+impl RelaxError {
+    /// Attributes this error to `span`, unless it already points somewhere
+    /// more specific.
+    pub fn at(self, span: Span) -> RelaxError {
+        match self {
+            RelaxError::At(..) => self,
+            error => RelaxError::At(Box::new(error), span),
+        }
+    }
+
+    /// This error without the location it was attributed to.
+    pub fn unlocated(self) -> RelaxError {
+        match self {
+            RelaxError::At(error, _) => *error,
+            error => error,
+        }
+    }
+
+    /// Formats this error, echoing the offending line of `source` with a
+    /// caret under the location it was attributed to.
+    pub fn render(&self, source: &str) -> String {
+        let RelaxError::At(error, span) = self else {
+            return self.to_string();
+        };
+        let Some(line) = source.lines().nth(span.line - 1) else {
+            return self.to_string();
+        };
+        let width = source.get(span.start..span.end)
+            .map_or(0, |text| text.chars().take_while( |c| *c != '\n').count())
+            .max(1);
+        let gutter = " ".repeat(span.line.to_string().len());
+
+        format!("{error}\n{gutter}--> line {}, column {}\n{} | {line}\n{gutter} | {}{}",
+            span.line, span.column, span.line, " ".repeat(span.column - 1), "^".repeat(width))
+    }
+}
+
+impl Display for RelaxError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            RelaxError::Lex(msg) => write!(f, "lex error: {msg}"),
+            RelaxError::Parse(msg) => write!(f, "parse error: {msg}"),
+            RelaxError::Schema(msg) => write!(f, "schema error: {msg}"),
+            RelaxError::Type(msg) => write!(f, "type error: {msg}"),
+            RelaxError::Arithmetic(msg) => write!(f, "arithmetic error: {msg}"),
+            RelaxError::Io(msg) => write!(f, "io error: {msg}"),
+            RelaxError::UnknownTable(name) => write!(f, "error: unknown table '{name}'"),
+            RelaxError::UnknownColumn(name) => write!(f, "error: unknown column '{name}'"),
+            RelaxError::AmbiguousColumn(name) => write!(f, "error: column '{name}' is ambiguous, qualify it with a relation name"),
+            RelaxError::InView(name, error) => write!(f, "{error} (in view '{name}')"),
+            RelaxError::At(error, span) => write!(f, "{error} (line {}, column {})", span.line, span.column),
+        }
+    }
+}
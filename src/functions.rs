@@ -0,0 +1,216 @@
+use crate::condition::Condition;
+use crate::tokenizer::{Span, Token};
+use crate::value::{Date, Decimal, Timestamp, Type, Value};
+use crate::error::RelaxError;
+
+/// The types a parameter accepts. An empty list accepts any type.
+type Parameter = &'static [Type];
+
+const ANY: Parameter = &[];
+const NUMBER: Parameter = &[Type::Integer, Type::Decimal];
+const STRING: Parameter = &[Type::String];
+const INTEGER: Parameter = &[Type::Integer];
+const DATE: Parameter = &[Type::Date, Type::Timestamp];
+const TIMESTAMP: Parameter = &[Type::Timestamp];
+
+/// A built-in scalar function that conditions can call by name.
+#[derive (Debug)]
+pub struct Function {
+    pub name: &'static str,
+    parameters: &'static [Parameter],
+    /// How many of the trailing parameters can be left out.
+    optional: usize,
+    /// Whether the last parameter can be repeated.
+    variadic: bool,
+    /// The type of the result, unless it depends on the arguments.
+    pub returns: Option<Type>,
+    /// Whether a Null argument makes the result Null. Functions that handle
+    /// Null themselves, like `coalesce`, are not strict.
+    strict: bool,
+    apply: fn(&[Value]) -> Result<Value, RelaxError>,
+}
+
+impl Function {
+    const fn new(name: &'static str, parameters: &'static [Parameter], returns: Option<Type>,
+        apply: fn(&[Value]) -> Result<Value, RelaxError>) -> Function {
+        Function { name, parameters, optional: 0, variadic: false, returns, strict: true, apply }
+    }
+
+    const fn optional(self, optional: usize) -> Function {
+        Function { optional, ..self }
+    }
+
+    const fn variadic(self) -> Function {
+        Function { variadic: true, ..self }
+    }
+
+    const fn handles_null(self) -> Function {
+        Function { strict: false, ..self }
+    }
+
+    /// Checks a call against this function's signature before it is ever
+    /// evaluated, using whatever argument types are known without a table.
+    pub fn check(&self, arguments: &[Condition], span: Span) -> Result<(), RelaxError> {
+        self.check_arity(arguments.len()).map_err( |error| error.at(span))?;
+        for (i, argument) in arguments.iter().enumerate() {
+            if let Some(ty) = argument.static_type() {
+                self.check_argument(i, ty).map_err( |error| error.at(argument.span()))?;
+            }
+        }
+        // The target of a cast has to be known up front
+        if self.name == "cast" {
+            match &arguments[1] {
+                Condition::Literal(Token::String(name), _) => { name.parse::<Type>().map_err( |error| error.at(arguments[1].span()))?; },
+                argument => return Err(RelaxError::Type("cast needs a type name like 'Integer'".to_string()).at(argument.span())),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn call(&self, arguments: &[Value]) -> Result<Value, RelaxError> {
+        if self.strict && arguments.contains(&Value::Null) {
+            return Ok(Value::Null);
+        }
+        for (i, argument) in arguments.iter().enumerate() {
+            if let Some(ty) = argument.ty() {
+                self.check_argument(i, ty)?;
+            }
+        }
+        (self.apply)(arguments)
+    }
+
+    fn check_arity(&self, count: usize) -> Result<(), RelaxError> {
+        let most = self.parameters.len();
+        let least = most - self.optional;
+        let expected = match (least, most) {
+            _ if self.variadic && count >= least => return Ok(()),
+            _ if (least..=most).contains(&count) => return Ok(()),
+            _ if self.variadic => format!("at least {least}"),
+            (least, most) if least == most => format!("{least}"),
+            (least, most) => format!("{least} to {most}"),
+        };
+        let plural = if expected == "1" { "" } else { "s" };
+        Err(RelaxError::Type(format!("{} takes {expected} argument{plural} but was given {count}", self.name)))
+    }
+
+    fn check_argument(&self, index: usize, ty: Type) -> Result<(), RelaxError> {
+        let parameter = self.parameters[index.min(self.parameters.len() - 1)];
+        if parameter.is_empty() || parameter.contains(&ty) {
+            return Ok(());
+        }
+        let expected = parameter.iter().map( |ty| ty.to_string()).collect::<Vec<String>>().join(" or ");
+        Err(RelaxError::Type(format!("{} expects {expected} for argument {} but found {ty}", self.name, index + 1)))
+    }
+}
+
+const FUNCTIONS: &[Function] = &[
+    Function::new("upper", &[STRING], Some(Type::String), |arguments| match arguments {
+        [Value::String(text)] => Ok(Value::String(text.to_uppercase())),
+        _ => unreachable!(),
+    }),
+    Function::new("lower", &[STRING], Some(Type::String), |arguments| match arguments {
+        [Value::String(text)] => Ok(Value::String(text.to_lowercase())),
+        _ => unreachable!(),
+    }),
+    Function::new("length", &[STRING], Some(Type::Integer), |arguments| match arguments {
+        [Value::String(text)] => Ok(Value::Integer(text.chars().count() as i64)),
+        _ => unreachable!(),
+    }),
+    // substr(text, start, length) counts characters from 1
+    Function::new("substr", &[STRING, INTEGER, INTEGER], Some(Type::String), |arguments| {
+        let [Value::String(text), Value::Integer(start), rest @ ..] = arguments else { unreachable!() };
+        let length = match rest {
+            [Value::Integer(length)] => (*length).max(0) as usize,
+            _ => usize::MAX,
+        };
+        let skip = start.saturating_sub(1).max(0) as usize;
+        // A start before the first character still counts towards the length
+        let length = length.saturating_sub(1i64.saturating_sub(*start).max(0) as usize);
+        Ok(Value::String(text.chars().skip(skip).take(length).collect()))
+    }).optional(1),
+    Function::new("concat", &[ANY], Some(Type::String), |arguments| {
+        Ok(Value::String(arguments.iter()
+            .filter( |value| **value != Value::Null)
+            .map( |value| value.to_string())
+            .collect()))
+    }).variadic().handles_null(),
+    Function::new("abs", &[NUMBER], None, |arguments| match arguments {
+        [Value::Integer(value)] => value.checked_abs().map(Value::Integer)
+            .ok_or_else( || RelaxError::Arithmetic(format!("abs({value}) overflows"))),
+        [Value::Decimal(value)] => Ok(Value::Decimal(value.abs())),
+        _ => unreachable!(),
+    }),
+    // round(number, digits) rounds half away from zero, to a whole number by default
+    Function::new("round", &[NUMBER, INTEGER], None, |arguments| {
+        let digits = match arguments.get(1) {
+            Some(Value::Integer(digits)) => u32::try_from(*digits)
+                .map_err( |_| RelaxError::Arithmetic(format!("cannot round to {digits} decimal places")))?,
+            _ => 0,
+        };
+        match &arguments[0] {
+            Value::Decimal(value) => Ok(Value::Decimal(value.round(digits))),
+            value => Ok(value.clone()),
+        }
+    }).optional(1),
+    Function::new("coalesce", &[ANY], None, |arguments| {
+        Ok(arguments.iter().find( |value| **value != Value::Null).cloned().unwrap_or(Value::Null))
+    }).variadic().handles_null(),
+    // cast(value, 'Type') converts between numbers, and to and from Strings
+    Function::new("cast", &[ANY, STRING], None, |arguments| {
+        let [value, Value::String(name)] = arguments else { unreachable!() };
+        let ty: Type = name.parse()?;
+        match (value, ty) {
+            (value, ty) if value.ty() == Some(ty) => Ok(value.clone()),
+            (value, Type::String) => Ok(Value::String(value.to_string())),
+            (Value::String(text), ty) => Value::parse(ty, text.trim()),
+            (Value::Integer(value), Type::Decimal) => Ok(Value::Decimal(Decimal::from(*value))),
+            (Value::Decimal(value), Type::Integer) => value.to_integer().map(Value::Integer)
+                .ok_or_else( || RelaxError::Arithmetic(format!("{value} is too large for an Integer"))),
+            (Value::Date(date), Type::Timestamp) => Ok(Value::Timestamp(Timestamp::from(*date))),
+            (Value::Timestamp(timestamp), Type::Date) => Ok(Value::Date(timestamp.date())),
+            (value, ty) => Err(RelaxError::Type(format!("cannot cast {} value '{value}' to {ty}", value.ty().unwrap()))),
+        }
+    }),
+    // date() also takes the date of a Timestamp
+    Function::new("date", &[&[Type::String, Type::Date, Type::Timestamp]], Some(Type::Date), |arguments| match arguments {
+        [Value::String(text)] => text.parse().map(Value::Date),
+        [value] => Ok(Value::Date(date_of(value))),
+        _ => unreachable!(),
+    }),
+    // timestamp() of a Date is midnight at its start
+    Function::new("timestamp", &[&[Type::String, Type::Date, Type::Timestamp]], Some(Type::Timestamp), |arguments| match arguments {
+        [Value::String(text)] => text.parse().map(Value::Timestamp),
+        [Value::Date(date)] => Ok(Value::Timestamp(Timestamp::from(*date))),
+        [value] => Ok(value.clone()),
+        _ => unreachable!(),
+    }),
+    Function::new("year", &[DATE], Some(Type::Integer), |arguments| Ok(Value::Integer(date_of(&arguments[0]).ymd().0))),
+    Function::new("month", &[DATE], Some(Type::Integer), |arguments| Ok(Value::Integer(date_of(&arguments[0]).ymd().1 as i64))),
+    Function::new("day", &[DATE], Some(Type::Integer), |arguments| Ok(Value::Integer(date_of(&arguments[0]).ymd().2 as i64))),
+    Function::new("hour", &[TIMESTAMP], Some(Type::Integer), |arguments| match arguments {
+        [Value::Timestamp(timestamp)] => Ok(Value::Integer(timestamp.hms().0 as i64)),
+        _ => unreachable!(),
+    }),
+    Function::new("minute", &[TIMESTAMP], Some(Type::Integer), |arguments| match arguments {
+        [Value::Timestamp(timestamp)] => Ok(Value::Integer(timestamp.hms().1 as i64)),
+        _ => unreachable!(),
+    }),
+    Function::new("second", &[TIMESTAMP], Some(Type::Integer), |arguments| match arguments {
+        [Value::Timestamp(timestamp)] => Ok(Value::Integer(timestamp.hms().2 as i64)),
+        _ => unreachable!(),
+    }),
+];
+
+/// The date of a Date or Timestamp argument.
+fn date_of(value: &Value) -> Date {
+    match value {
+        Value::Date(date) => *date,
+        Value::Timestamp(timestamp) => timestamp.date(),
+        _ => unreachable!(),
+    }
+}
+
+/// The built-in function called `name`, ignoring case.
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find( |function| function.name.eq_ignore_ascii_case(name))
+}
@@ -2,81 +2,157 @@ mod tokenizer;
 mod parser;
 mod table;
 mod condition;
+mod functions;
+mod value;
+mod error;
+mod csv;
 
 use std::io::{self, Write};
+use std::{env, fs, process};
 use std::collections::HashMap;
+use std::iter::zip;
 use crate::tokenizer::*;
 use crate::table::Table;
+use crate::parser::{Relation, Statement};
+use crate::value::{Type, Value, Date, Timestamp};
+use crate::error::RelaxError;
 
 
-fn process_table_tokens(tokens: Vec<Vec<Token>>) -> Result<Vec<Vec<Token>>, String> {
-    let mut result: Vec<Vec<Token>> = Vec::new();
-    let mut tokens = tokens.iter()
-        .flatten()
-        .skip_while( |token| **token != Token::OpenCurly )
-        .peekable();
-
-    let mut row: Vec<Token> = Vec::new();
-    while let Some(token) = tokens.next() {
-        match token {
-            Token::CloseCurly => {return Ok(result);},
-            Token::Comma | Token::EOF | Token::OpenCurly => {},
-            Token::Symbol(_) | Token::Number(_) | Token::String(_)=> {
-                row.push(token.clone());
-                if *tokens.peek().unwrap() != &Token::Comma {
-                    result.push(row.clone());
-                    row.clear();
-                }
-            },
-            _ => { return Err(format!("error: unable to parse token {:?} while processing table", token)); },
+fn build_table(tokens: Vec<Vec<SpannedToken>>) -> Result<Table, RelaxError> {
+    if tokens.len() < 2 {
+        return Err(RelaxError::Schema("table must have at least 2 rows".to_string()));
+    }
+    let headers = tokens[0].iter().map( |spanned| {
+        match &spanned.token {
+            Token::Symbol(name) => Ok(name.clone()),
+            token => Err(RelaxError::Schema(format!("table headers must be names, found {token:?}")).at(spanned.span)),
+        }
+        }).collect::<Result<Vec<String>, RelaxError>>()?;
+    let rows = tokens[1..].iter()
+        .map( |row| row.iter()
+            .map( |spanned| Value::from_token(&spanned.token).map_err( |error| error.at(spanned.span)))
+            .collect::<Result<Vec<Value>, RelaxError>>())
+        .collect::<Result<Vec<Vec<Value>>, RelaxError>>()?;
+    // Each column takes the type of its first non-null value, widened to
+    // Decimal if it mixes Integers and Decimals. Strings that are all
+    // YYYY-MM-DD dates make a Date column, and ones that are all
+    // YYYY-MM-DD HH:MM:SS timestamps make a Timestamp column.
+    let types = (0..headers.len()).map( |i| {
+        let column = || rows.iter().filter_map( |row| row.get(i));
+        let mut types = column().filter_map( |value| value.ty());
+        // A column of only Nulls has nothing to infer from
+        let Some(first) = types.next() else {
+            return Type::String;
+        };
+        let is_date = |value: &Value| match value {
+            Value::String(text) => text.parse::<Date>().is_ok(),
+            value => *value == Value::Null,
         };
+        let is_timestamp = |value: &Value| match value {
+            Value::String(text) => text.parse::<Timestamp>().is_ok(),
+            value => *value == Value::Null,
+        };
+        if first == Type::Integer && types.any( |ty| ty == Type::Decimal) {
+            Type::Decimal
+        } else if first == Type::String && column().all(is_date) {
+            Type::Date
+        } else if first == Type::String && column().all(is_timestamp) {
+            Type::Timestamp
+        } else {
+            first
+        }
+        }).collect::<Vec<Type>>();
+    let mut table = Table::new(headers, types);
+    let width = table.headers.len();
+    for (row, mut values) in zip(&tokens[1..], rows) {
+        if row.len() != width {
+            let span = row[0].span.to(row[row.len() - 1].span);
+            return Err(RelaxError::Schema(format!("all table rows must have {width} values")).at(span));
+        }
+        for (i, (spanned, value)) in zip(row, &mut values).enumerate() {
+            match (table.types[i], &value) {
+                (Type::Decimal, Value::Integer(_)) => *value = Value::Decimal(value.to_decimal().unwrap()),
+                (Type::Date, Value::String(text)) => *value = Value::Date(text.parse().map_err( |error: RelaxError| error.at(spanned.span))?),
+                (Type::Timestamp, Value::String(text)) => *value = Value::Timestamp(text.parse().map_err( |error: RelaxError| error.at(spanned.span))?),
+                _ => {},
+            }
+            if value.ty().is_some_and( |ty| ty != table.types[i]) {
+                return Err(RelaxError::Type(format!("cannot store {:?} in {} column '{}'", spanned.token, table.types[i], table.headers[i])).at(spanned.span));
+            }
+        }
+        table.rows.push(values);
     }
-    Ok(result)
+
+    Ok(table)
 }
 
-fn build_table(mut tokens: Vec<Vec<Token>>) -> Table {
-    if tokens.len() < 2 {
-        panic!("error: table must have at least 2 rows"); 
-    }
-    let mut table = Table { 
-        rows: Vec::new(),
-        types: tokens[1].iter_mut().map( |token| {
-            match token {
-               Token::Number(_) => "Integer".to_string(),
-               Token::String(_) => "String".to_string(),
-               _ => panic!("error: unable to identify table types"),
+/// Whether `tokens` open more `{` than they close.
+fn unclosed(tokens: &[SpannedToken]) -> bool {
+    let opened = tokens.iter().filter( |spanned| spanned.token == Token::OpenCurly).count();
+    let closed = tokens.iter().filter( |spanned| spanned.token == Token::CloseCurly).count();
+    opened > closed
+}
+
+/// Runs one statement against `tables`, printing any table it produces.
+/// Only the REPL reports on tables it creates.
+fn execute(statement: Statement, tables: &mut HashMap<String, Relation>, interactive: bool) -> Result<(), RelaxError> {
+    match statement {
+        Statement::Query(expr) => println!("{}", expr.eval(tables)?),
+        Statement::Create { name, rows, span } => {
+            let table = build_table(rows).map_err( |error| error.at(span))?;
+            if interactive {
+                println!("Built new table: {table:?}");
             }
-            }).collect::<Vec<String>>(),
+            tables.insert(name, Relation::Table(table));
+        },
+        Statement::Assign { name, expr } => {
+            let table = expr.eval(tables)?;
+            tables.insert(name, Relation::Table(table));
+        },
+        Statement::View { name, expr, span } => {
+            parser::check_view(&name, &expr, tables).map_err( |error| error.at(span))?;
+            tables.insert(name, Relation::View(expr));
+        },
+        Statement::Load { name, path, span } => {
+            let table = csv::load(&path).map_err( |error| error.at(span))?;
+            if interactive {
+                println!("Loaded {} rows into {name}", table.rows.len());
+            }
+            tables.insert(name, Relation::Table(table));
+        },
+        Statement::Save { expr, path, span } => {
+            let table = expr.eval(tables)?;
+            csv::save(&table, &path).map_err( |error| error.at(span))?;
+        },
+    }
+    Ok(())
+}
+
+/// Runs the statements in `source` in order without prompting, stopping at
+/// the first one that fails to parse or run.
+fn run(source: &str, tables: &mut HashMap<String, Relation>) -> Result<(), RelaxError> {
+    // Statements before a bad character still run, so lex only up to it
+    let (tokens, lex_error) = match get_tokens(source) {
+        Ok(tokens) => (tokens, None),
+        Err(RelaxError::At(error, span)) => (get_tokens(&source[..span.start])?, Some((RelaxError::At(error, span), span))),
+        Err(error) => return Err(error),
     };
-    let width = tokens[0].len();
-    for row in tokens.iter_mut() {
-        if row.len() != width {
-            panic!("error: all table rows must be same length");// TODO: shouldn't panic
+    let mut statements = parser::statements(&tokens);
+    while let Some(statement) = statements.next() {
+        if let Some((error, span)) = &lex_error {
+            // The statement the bad character belongs to is cut short, so it
+            // never runs
+            if statement.is_err() || (statements.at_end() && statements.span().line == span.line) {
+                return Err(error.clone());
+            }
         }
-        table.rows.push(row.iter_mut()
-            .enumerate().map( |(i, token)| {
-                match token {
-                    Token::Symbol(string) => string.clone(),
-                    Token::Number(string) => {
-                        if table.types[i] != "Integer" {panic!("error: cannot store non-Integer values in Integer coloumn")};
-                        string.clone()
-                    },
-                    Token::String(string) => {
-                        if table.types[i] != "String" {panic!("error: cannot store non-String values in String coloumn")};
-                        string.clone()
-                    },
-                    _ => panic!("error: unable to build row"),
-                }
-            })
-            .collect()
-        );
+        execute(statement?, tables, false)?;
     }
-
-    println!("Built new table: {table:?}");
-
-    table
+    lex_error.map_or(Ok(()), |(error, _)| Err(error))
 }
 
+const USAGE: &str = "usage: rust-relax [run <file> | -e <statements>]";
+
 fn main() {
     // let test_string = r#"
     // Student = {ID, Name, Age, Major
@@ -87,20 +163,70 @@ fn main() {
     // "#;
 
 
-    let mut tables: HashMap<String, Table> = HashMap::new();
-    tables.insert("a".to_string(), Table::new(vec!["test".to_string()]));
+    let mut tables: HashMap<String, Relation> = HashMap::new();
+    tables.insert("a".to_string(), Relation::Table(Table::new(vec!["test".to_string()], vec![Type::String])));
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let source = match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        [] => {
+            repl(&mut tables);
+            return;
+        },
+        ["run", path] => match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("error: unable to read '{path}': {error}");
+                process::exit(1);
+            },
+        },
+        ["-e", source] => source.to_string(),
+        _ => {
+            eprintln!("{USAGE}");
+            process::exit(2);
+        },
+    };
+    if let Err(error) = run(&source, &mut tables) {
+        eprintln!("{}", error.render(&source));
+        process::exit(1);
+    }
+
+    //Table::new(vec!["1".to_string(), "2".to_string()]);
+}
 
+/// Reads and runs statements from stdin until `quit` or end of input.
+fn repl(tables: &mut HashMap<String, Relation>) {
     loop {
         print!("> ");
         io::stdout().flush().expect("error: unable to flush stdout");
 
         let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("error: unable to read user input");
-        let mut tokens: Vec<Token> = get_tokens(input.chars());
+        if io::stdin().read_line(&mut input).expect("error: unable to read user input") == 0 {
+            println!("Exiting... Have a nice day!");
+            break;
+        }
+        let mut tokens = get_tokens(&input);
+        // A table literal continues until its '}' or a blank line
+        while tokens.as_ref().is_ok_and( |tokens| unclosed(tokens)) {
+            let mut line = String::new();
+            print!("\t");
+            io::stdout().flush().expect("error: unable to flush stdout");
+            io::stdin().read_line(&mut line).expect("error: unable to read user input");
+            if line.trim().is_empty() {
+                break;
+            }
+            input.push_str(&line);
+            tokens = get_tokens(&input);
+        }
+        let tokens: Vec<SpannedToken> = match tokens {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                eprintln!("{}", error.render(&input));
+                continue;
+            },
+        };
         //println!("Tokens: {tokens:?}");
-        // TODO: Handle newline
         if tokens.len() == 2 {
-            if let Token::Symbol(command) = &tokens[0] {
+            if let Token::Symbol(command) = &tokens[0].token {
                 match command.to_lowercase().as_str() {
                     "quit" | "exit" => {
                         println!("Exiting... Have a nice day!");
@@ -108,48 +234,37 @@ fn main() {
                     },
                     "help" | "h" => {
                         println!("Need help?");
+                        continue;
                     },
                     _ => {},
                 }
             }
         }
-        if tokens[0] == Token::EOF {
-            println!("Exiting... Have a nice day!");
-            break;
-        } else if tokens[1] == Token::Equals {
-            let Token::Symbol(table_name) = tokens[0].clone() else {break;};
-            let mut table_tokens = vec![tokens.clone()];
-
-            while tokens[0] != Token::EOF {
-                let mut input = String::new();
-                print!("\t");
-                io::stdout().flush().expect("error: unable to flush stdout");
-                io::stdin().read_line(&mut input).expect("error: unable to read user input");
-                tokens = get_tokens(input.chars());
-                table_tokens.push(tokens.clone());
-                //println!("Tokens: {tokens:?}");
-            }
-
-            let result = process_table_tokens(table_tokens);
-            match result {
-                Ok(toks) => {tables.insert(table_name.to_string(), build_table(toks));},
-                Err(msg) => {eprintln!("{msg}");},
-            };
-
-        } else {
-            tokens.pop(); // Remove EOF
-
-            let tree = parser::parse(&tokens);
 
-            let result = tree.eval(&tables);
-            match result {
-                Some(table) => println!("{table}"),
-                None => println!("No table found"),
-            };
+        // Statements run in order, stopping at the first error
+        for statement in parser::statements(&tokens) {
+            if let Err(error) = statement.and_then( |statement| execute(statement, tables, true)) {
+                eprintln!("{}", error.render(&input));
+                break;
+            }
         }
     }
-
-    //Table::new(vec!["1".to_string(), "2".to_string()]);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    fn table(source: &str) -> Table {
+        let tokens = get_tokens(source).unwrap();
+        let Some(Ok(Statement::Create { rows, .. })) = parser::statements(&tokens).next() else { panic!("expected a table") };
+        build_table(rows).unwrap()
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let table = table("T = {a, b, c, d, e\n1, 1, '2024-01-24', '2024-01-24 08:30', null\n2, 2.5, null, null, null}");
+        assert_eq!(table.types, vec![Type::Integer, Type::Decimal, Type::Date, Type::Timestamp, Type::String]);
+        assert_eq!(table.rows[0][1], Value::Decimal(1.into()));
+    }
+}
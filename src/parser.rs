@@ -1,134 +1,315 @@
-use crate::tokenizer::Token;
+use crate::tokenizer::{Span, SpannedToken, Token, TokenStream};
 use crate::table::Table;
 use crate::condition::{Condition, self};
-use std::slice::Iter;
-use std::iter::Peekable;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use crate::error::RelaxError;
 
 pub type List = Vec<String>;
 
+#[derive (Debug)]
+pub enum Rename {
+    Relation(String),
+    /// `(new_name, old_name)` pairs.
+    Columns(Vec<(String, String)>),
+}
+
+#[derive (Debug, Clone, Copy, PartialEq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// An aggregate column of a grouping, e.g. `sum(salary)->total`.
+#[derive (Debug)]
+pub struct Aggregate {
+    pub function: AggregateFunction,
+    /// The aggregated column, or `None` for `count(*)`.
+    pub column: Option<String>,
+    pub name: String,
+}
+
+/// A column of an extended projection: an existing column, or a computed
+/// expression such as `salary * 2 -> doubled`.
+#[derive (Debug)]
+pub struct ProjectColumn {
+    pub expression: Box<Condition>,
+    pub name: Option<String>,
+}
+
+/// A column to sort on, e.g. `salary desc`.
+#[derive (Debug)]
+pub struct SortKey {
+    pub column: String,
+    pub descending: bool,
+}
+
 #[derive (Debug)]
 pub enum Expr {
-    Binary { left: Box<Expr>, operator: Token, right: Box<Expr>},
-    BinaryCond{ left: Box<Expr>, operator: Token, condition: Box<Condition>, right: Box<Expr> },
-    Unary { operator: Token, right: Box<Expr> },
-    UnaryCond { operator: Token, condition: Box<Condition> ,right: Box<Expr> },
+    Binary { left: Box<Expr>, operator: Token, right: Box<Expr>, span: Span },
+    BinaryCond{ left: Box<Expr>, operator: Token, condition: Box<Condition>, right: Box<Expr>, span: Span },
+    UnaryCond { operator: Token, condition: Box<Condition> ,right: Box<Expr>, span: Span },
     Grouping(Box<Expr>),
-    Literal(Token),
-    UnaryList{ operator: Token, list: Box<List> ,right: Box<Expr> },
+    Literal(Token, Span),
+    UnaryProject{ operator: Token, columns: Vec<ProjectColumn>, right: Box<Expr>, span: Span },
+    UnaryRename{ operator: Token, rename: Box<Rename>, right: Box<Expr>, span: Span },
+    UnaryGroup{ operator: Token, group: Box<List>, aggregates: Vec<Aggregate>, right: Box<Expr>, span: Span },
+    UnarySort{ operator: Token, keys: Vec<SortKey>, right: Box<Expr>, span: Span },
 }
 
 
 impl Expr {
-    pub fn eval<'a>(&'a self, tables: &'a HashMap<String, Table>) -> Option<Table> {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Binary { span, .. } | Expr::BinaryCond { span, .. } | Expr::UnaryCond { span, .. }
+                | Expr::UnaryProject { span, .. } | Expr::UnaryRename { span, .. } | Expr::UnaryGroup { span, .. }
+                | Expr::UnarySort { span, .. } | Expr::Literal(_, span) => *span,
+            Expr::Grouping(expr) => expr.span(),
+        }
+    }
+
+    /// The names of the relations this expression reads from.
+    fn relations(&self) -> Vec<&str> {
+        match self {
+            Expr::Binary { left, right, .. } | Expr::BinaryCond { left, right, .. } => {
+                let mut relations = left.relations();
+                relations.extend(right.relations());
+                relations
+            },
+            Expr::UnaryCond { right, .. } | Expr::UnaryProject { right, .. } | Expr::UnaryRename { right, .. }
+                | Expr::UnaryGroup { right, .. } | Expr::UnarySort { right, .. } => right.relations(),
+            Expr::Grouping(expr) => expr.relations(),
+            Expr::Literal(Token::Symbol(key), _) => vec![key],
+            Expr::Literal(..) => vec![],
+        }
+    }
+
+    pub fn eval<'a>(&'a self, tables: &'a HashMap<String, Relation>) -> Result<Table, RelaxError> {
+        self.eval_node(tables).map_err( |error| error.at(self.span()))
+    }
+
+    fn eval_node<'a>(&'a self, tables: &'a HashMap<String, Relation>) -> Result<Table, RelaxError> {
         match self {
-            Expr::Binary { left, operator, right } => {
+            Expr::Binary { left, operator, right, .. } => {
+                let left = left.eval(tables)?;
+                let right = right.eval(tables)?;
                 match operator {
-                    Token::Union => {Some(left.eval(&tables).unwrap().union(&right.eval(&tables).unwrap()).unwrap())},
-                    Token::Intersect => {Some(left.eval(&tables).unwrap().intersect(&right.eval(&tables).unwrap()).unwrap())},
-                    Token::Minus => {Some(left.eval(&tables).unwrap().minus(&right.eval(&tables).unwrap()).unwrap())},
-                    Token::Multiply => {Some(left.eval(&tables).unwrap().multiply(&right.eval(&tables).unwrap()).unwrap())},
-                    Token::Divide => {Some(left.eval(&tables).unwrap().divide(&right.eval(&tables).unwrap()).unwrap())},
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Union => left.union(&right),
+                    Token::Intersect => left.intersect(&right),
+                    Token::Minus => left.minus(&right),
+                    Token::Multiply => left.multiply(&right),
+                    Token::Divide => left.divide(&right),
+                    Token::NaturalJoin => left.natural_join(&right),
+                    Token::LeftSemiJoin => left.left_semi_join(&right),
+                    Token::RightSemiJoin => right.left_semi_join(&left),
+                    Token::AntiJoin => left.anti_join(&right),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
                 }
             },
-            Expr::BinaryCond { left, operator, condition, right } => {
+            Expr::BinaryCond { left, operator, condition, right, .. } => {
+                let left = left.eval(tables)?;
+                let right = right.eval(tables)?;
                 match operator {
-                    Token::Join => {Some(left.eval(&tables).unwrap().join(&condition, &right.eval(&tables).unwrap()).unwrap())},
-                    Token::LeftJoin => {Some(left.eval(&tables).unwrap().left_join(&condition, &right.eval(&tables).unwrap()).unwrap())},
-                    Token::RightJoin => {Some(left.eval(&tables).unwrap().right_join(&condition, &right.eval(&tables).unwrap()).unwrap())},
-                    Token::FullJoin => {Some(left.eval(&tables).unwrap().full_join(&condition, &right.eval(&tables).unwrap()).unwrap())},
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Join => left.join(condition, &right),
+                    Token::LeftJoin => left.left_join(condition, &right),
+                    Token::RightJoin => left.right_join(condition, &right),
+                    Token::FullJoin => left.full_join(condition, &right),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
                 }
             },
-            Expr::Unary{ operator, .. } => {
+            Expr::UnaryCond{ operator, condition ,right, .. } => {
                 match operator {
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Select => right.eval(tables)?.select(condition),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
+                }
+            },
+            Expr::UnaryProject{ operator, columns, right, .. } => {
+                match operator {
+                    Token::Project => right.eval(tables)?.extended_project(columns),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
+                }
+            },
+            Expr::UnaryRename{ operator, rename, right, .. } => {
+                match (operator, rename.as_ref()) {
+                    (Token::Rename, Rename::Relation(name)) => Ok(right.eval(tables)?.rename_relation(name)),
+                    (Token::Rename, Rename::Columns(columns)) => right.eval(tables)?.rename_columns(columns),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
                 }
             },
-            Expr::UnaryCond{ operator, condition ,right } => {
+            Expr::UnaryGroup{ operator, group, aggregates, right, .. } => {
                 match operator {
-                    Token::Select => {Some(right.eval(&tables).unwrap().select(&condition).unwrap())},
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Gamma => right.eval(tables)?.group_by(group, aggregates),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
                 }
             },
-            Expr::UnaryList{ operator, list, right } => {
+            Expr::UnarySort{ operator, keys, right, .. } => {
                 match operator {
-                    Token::Project => {Some(right.eval(&tables).unwrap().project(list.to_vec()).unwrap())},
-                    _ => panic!("error: can't evaluate {operator:?}"),
+                    Token::Tau => right.eval(tables)?.sort_by(keys),
+                    _ => Err(RelaxError::Parse(format!("can't evaluate {operator:?}"))),
                 }
             },
-            Expr::Literal(token) => {
-                match token {
-                    Token::Symbol(key) => tables.get(key).cloned(),
-                    _ => panic!("error: can't evaluate {token:?} Literal"),
+            Expr::Literal(Token::Symbol(key), _) => {
+                match tables.get(key) {
+                    Some(Relation::Table(table)) => Ok(table.rename_relation(key)),
+                    // A view's spans point into the input that defined it, so
+                    // its errors are reported at the reference instead
+                    Some(Relation::View(expr)) => expr.eval(tables)
+                        .map( |table| table.rename_relation(key))
+                        .map_err( |error| RelaxError::InView(key.clone(), Box::new(error.unlocated()))),
+                    None => Err(RelaxError::UnknownTable(key.clone())),
                 }
             },
-            Expr::Grouping(expr) => expr.eval(&tables),
+            Expr::Literal(token, _) => Err(RelaxError::Parse(format!("{token:?} is not a table"))),
+            Expr::Grouping(expr) => expr.eval(tables),
         }
     }
 }
 
-fn expr(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Expr> { 
-    let mut expr = factor(tokens);
+/// A named relation: either a stored table, or a view whose expression is
+/// evaluated against the current relations each time it is used.
+#[derive (Debug)]
+pub enum Relation {
+    Table(Table),
+    View(Box<Expr>),
+}
+
+/// Checks that defining view `name` as `expr` wouldn't make it depend on
+/// itself. Views are the only way to create a cycle, so checking each one
+/// as it is defined keeps evaluation from recursing forever.
+pub fn check_view(name: &str, expr: &Expr, relations: &HashMap<String, Relation>) -> Result<(), RelaxError> {
+    let mut pending = expr.relations();
+    let mut seen = HashSet::new();
+    while let Some(relation) = pending.pop() {
+        if relation == name {
+            return Err(RelaxError::Schema(format!("view '{name}' would depend on itself")));
+        }
+        if let (true, Some(Relation::View(view))) = (seen.insert(relation), relations.get(relation)) {
+            pending.extend(view.relations());
+        }
+    }
+    Ok(())
+}
+
+fn expr(tokens: &mut TokenStream) -> Result<Box<Expr>, RelaxError> {
+    let mut expr = factor(tokens)?;
 
     while let Some(token) = tokens.peek() {
         match token {
             Token::Plus | Token::Minus | Token::Union | Token::Intersect => {
                 let operator = tokens.next().unwrap().clone();
-                let right = factor(tokens);
-                expr = Box::new(Expr::Binary {left: expr, operator, right}); 
+                let right = factor(tokens)?;
+                let span = expr.span().to(right.span());
+                expr = Box::new(Expr::Binary {left: expr, operator, right, span});
             }
             _ => break,
         }
     }
 
-    expr
+    Ok(expr)
 }
 
-fn factor(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Expr> { 
-    let mut expr = unary(tokens);
+fn factor(tokens: &mut TokenStream) -> Result<Box<Expr>, RelaxError> {
+    let mut expr = unary(tokens)?;
 
     while let Some(token) = tokens.peek() {
         match token {
-            Token::Multiply | Token::Divide => {
+            Token::Multiply | Token::Divide | Token::NaturalJoin | Token::LeftSemiJoin | Token::RightSemiJoin | Token::AntiJoin => {
                 let operator = tokens.next().unwrap().clone();
-                let right = unary(tokens);
-                let new_expr = Expr::Binary { left: expr, operator, right};
+                let right = unary(tokens)?;
+                let span = expr.span().to(right.span());
+                let new_expr = Expr::Binary { left: expr, operator, right, span };
                 expr = Box::new(new_expr);
             },
-            Token::Join | Token::LeftJoin | Token::RightJoin | Token::FullJoin => {
+            Token::Join if tokens.peek_nth(1) == Some(&Token::OpenBracket) => {
                 let operator = tokens.next().unwrap().clone();
-                let condition = condition::parse(tokens);
-                let right = unary(tokens);
-                expr = Box::new(Expr::BinaryCond { left: expr, operator, condition, right });
+                let condition = subscript(tokens, condition::parse)?;
+                let right = unary(tokens)?;
+                let span = expr.span().to(right.span());
+                expr = Box::new(Expr::BinaryCond { left: expr, operator, condition, right, span });
+            },
+            Token::Join => {
+                // Like ⋈, a join without a condition is a natural join
+                tokens.next();
+                let start = tokens.position();
+                let mut ambiguous = true;
+                let theta = condition::parse(tokens).and_then( |condition| {
+                    // A bare name could be the right operand of a natural
+                    // join instead, and is taken as one if nothing follows
+                    // it on the same line
+                    ambiguous = condition.is_name();
+                    if ambiguous && tokens.peek_span().line > tokens.span().line {
+                        return Err(RelaxError::Parse("expected a relation after the join condition".to_string()).at(tokens.peek_span()));
+                    }
+                    Ok((condition, unary(tokens)?))
+                });
+                match theta {
+                    Ok((condition, right)) => {
+                        let span = expr.span().to(right.span());
+                        expr = Box::new(Expr::BinaryCond { left: expr, operator: Token::Join, condition, right, span });
+                    },
+                    Err(error) if ambiguous => {
+                        tokens.rewind(start);
+                        let right = unary(tokens).map_err( |_| error)?;
+                        let span = expr.span().to(right.span());
+                        expr = Box::new(Expr::Binary { left: expr, operator: Token::NaturalJoin, right, span });
+                    },
+                    Err(error) => return Err(error),
+                }
+            },
+            Token::LeftJoin | Token::RightJoin | Token::FullJoin => {
+                let operator = tokens.next().unwrap().clone();
+                let condition = subscript(tokens, condition::parse)?;
+                let right = unary(tokens)?;
+                let span = expr.span().to(right.span());
+                expr = Box::new(Expr::BinaryCond { left: expr, operator, condition, right, span });
             }
             _ => break,
         }
     }
 
-    expr
+    Ok(expr)
 }
 
-fn unary(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Expr> { 
+fn unary(tokens: &mut TokenStream) -> Result<Box<Expr>, RelaxError> {
+    let start = tokens.peek_span();
     if let Some(token) = tokens.peek() {
         match token {
-            Token::Minus => {
+            Token::Select=> {
                 let operator = tokens.next().unwrap().clone();
-                let right = unary(tokens);
-                return Box::new(Expr::Unary {operator, right})
+                let condition = subscript(tokens, condition::parse)?;
+                let right = unary(tokens)?;
+                let span = start.to(right.span());
+                return Ok(Box::new(Expr::UnaryCond {operator, condition, right, span}))
             },
-Token::Select=> {
+            Token::Project => {
                 let operator = tokens.next().unwrap().clone();
-                let condition = condition::parse(tokens);
-                let right = unary(tokens);
-                return Box::new(Expr::UnaryCond {operator, condition, right})
+                let columns = subscript(tokens, project_columns)?;
+                let right = unary(tokens)?;
+                let span = start.to(right.span());
+                return Ok(Box::new(Expr::UnaryProject {operator, columns, right, span}))
             },
-            Token::Project => {
+            Token::Rename => {
                 let operator = tokens.next().unwrap().clone();
-                let list = Box::new(list(tokens));
-                let right = unary(tokens);
-                return Box::new(Expr::UnaryList {operator, list, right})
+                let rename = Box::new(subscript(tokens, rename)?);
+                let right = unary(tokens)?;
+                let span = start.to(right.span());
+                return Ok(Box::new(Expr::UnaryRename {operator, rename, right, span}))
+            },
+            Token::Gamma => {
+                let operator = tokens.next().unwrap().clone();
+                let (group, aggregates) = subscript(tokens, grouping)?;
+                let right = unary(tokens)?;
+                let span = start.to(right.span());
+                return Ok(Box::new(Expr::UnaryGroup {operator, group, aggregates, right, span}))
+            },
+            Token::Tau => {
+                let operator = tokens.next().unwrap().clone();
+                let keys = subscript(tokens, sort_keys)?;
+                let right = unary(tokens)?;
+                let span = start.to(right.span());
+                return Ok(Box::new(Expr::UnarySort {operator, keys, right, span}))
             },
             _ => {},
         }
@@ -136,37 +317,62 @@ Token::Select=> {
     primary(tokens)
 }
 
-fn primary(tokens: &mut Peekable<Iter<'_, Token>>) -> Box<Expr> { 
-    if let Some(token) = tokens.next() {
-        match token {
-           Token::Symbol(_) | Token::Number(_) | Token::String(_) => Box::new(Expr::Literal(token.clone())),
-            Token::OpenParen => { 
-                let expr = expr(tokens);
-                if tokens.next().unwrap() != &Token::CloseParen {panic!("error: expected ')' after expression")};
-                Box::new(Expr::Grouping(expr))
-            },
-            token => panic!("error: unable to parse {:?}", token),
-        }
-    } else {
-        panic!("error: no more tokens");
+/// Parses the arguments of an operator. They can be written inline, or
+/// in brackets like `select[a > 1](R)` to mark exactly where they end.
+fn subscript<T>(tokens: &mut TokenStream, arguments: fn(&mut TokenStream) -> Result<T, RelaxError>) -> Result<T, RelaxError> {
+    if tokens.peek() != Some(&Token::OpenBracket) {
+        return arguments(tokens);
+    }
+    tokens.next();
+    let result = arguments(tokens)?;
+    if tokens.next() != Some(&Token::CloseBracket) {
+        return Err(RelaxError::Parse("expected ']' after operator arguments".to_string()).at(tokens.span()));
+    }
+    Ok(result)
+}
+
+/// Parses gamma's grouping columns and aggregates, separated by `;`.
+fn grouping(tokens: &mut TokenStream) -> Result<(Box<List>, Vec<Aggregate>), RelaxError> {
+    let group = Box::new(match tokens.peek() {
+        Some(Token::Semicolon) => vec![],
+        _ => list(tokens)?,
+    });
+    if tokens.next() != Some(&Token::Semicolon) {
+        return Err(RelaxError::Parse("expected ';' after grouping columns".to_string()).at(tokens.span()));
+    }
+    Ok((group, aggregates(tokens)?))
+}
+
+fn primary(tokens: &mut TokenStream) -> Result<Box<Expr>, RelaxError> {
+    match tokens.next() {
+        Some(token @ (Token::Symbol(_) | Token::Number(_) | Token::String(_))) => Ok(Box::new(Expr::Literal(token.clone(), tokens.span()))),
+        Some(Token::OpenParen) => {
+            let expr = expr(tokens)?;
+            if tokens.next() != Some(&Token::CloseParen) {
+                return Err(RelaxError::Parse("expected ')' after expression".to_string()).at(tokens.span()));
+            }
+            Ok(Box::new(Expr::Grouping(expr)))
+        },
+        Some(Token::Eof) | None => Err(RelaxError::Parse("unexpected end of expression".to_string()).at(tokens.span())),
+        Some(token) => Err(RelaxError::Parse(format!("unable to parse {token:?}")).at(tokens.span())),
     }
 }
 
-fn list(tokens: &mut Peekable<Iter<'_, Token>>) -> List { 
+fn list(tokens: &mut TokenStream) -> Result<List, RelaxError> {
     let mut result: Vec<String> = Vec::new();
-    let Token::Symbol(string) = tokens.next().unwrap() else {
-        panic!("error: list expected");
+    let Some(Token::Symbol(string)) = tokens.next() else {
+        return Err(RelaxError::Parse("list expected".to_string()).at(tokens.span()));
     };
     result.push(string.clone());
 
-    while let Some(&token) = tokens.peek() {
+    while let Some(token) = tokens.peek() {
         match token {
             Token::Comma => {
                 tokens.next();
                 match tokens.next() {
                     Some(Token::Symbol(symbol)) => {result.push(symbol.to_string())},
-                    None => panic!("error: unexpected end of list"),
-                    _ => panic!("error: list must only contain variables"),
+                    Some(Token::Eof) | None => return Err(RelaxError::Parse("unexpected end of list".to_string()).at(tokens.span())),
+                    _ => return Err(RelaxError::Parse("list must only contain variables".to_string()).at(tokens.span())),
                 }
             },
             _ => {
@@ -175,10 +381,388 @@ fn list(tokens: &mut Peekable<Iter<'_, Token>>) -> List {
         }
     }
 
-    result
+    Ok(result)
+}
+
+/// Parses a comma separated list of expressions, each optionally named with `-> name`.
+/// Anything other than a plain column must be named.
+fn project_columns(tokens: &mut TokenStream) -> Result<Vec<ProjectColumn>, RelaxError> {
+    let mut result = vec![];
+    loop {
+        let expression = condition::parse(tokens)?;
+        let name = match tokens.peek() {
+            Some(Token::Arrow) => {
+                tokens.next();
+                let Some(Token::Symbol(name)) = tokens.next() else {
+                    return Err(RelaxError::Parse("expected a name after '->'".to_string()).at(tokens.span()));
+                };
+                Some(name.clone())
+            },
+            _ if !matches!(*expression, Condition::Literal(Token::Symbol(_), _)) => {
+                return Err(RelaxError::Parse("computed columns must be named with '->'".to_string()).at(expression.span()));
+            },
+            _ => None,
+        };
+        result.push(ProjectColumn { expression, name });
+
+        if tokens.peek() != Some(&Token::Comma) {
+            return Ok(result);
+        }
+        tokens.next();
+    }
+}
+
+/// Parses a comma separated list of `function(column)->name` aggregates.
+fn aggregates(tokens: &mut TokenStream) -> Result<Vec<Aggregate>, RelaxError> {
+    let mut result = vec![];
+    loop {
+        let function = match tokens.next() {
+            Some(Token::Symbol(name)) => match name.to_lowercase().as_str() {
+                "count" => AggregateFunction::Count,
+                "sum" => AggregateFunction::Sum,
+                "avg" => AggregateFunction::Avg,
+                "min" => AggregateFunction::Min,
+                "max" => AggregateFunction::Max,
+                _ => return Err(RelaxError::Parse(format!("unknown aggregate function '{name}'")).at(tokens.span())),
+            },
+            _ => return Err(RelaxError::Parse("expected an aggregate function".to_string()).at(tokens.span())),
+        };
+        if tokens.next() != Some(&Token::OpenParen) {
+            return Err(RelaxError::Parse("expected '(' after aggregate function".to_string()).at(tokens.span()));
+        }
+        let column = match tokens.next() {
+            Some(Token::Multiply) if function == AggregateFunction::Count => None,
+            Some(Token::Symbol(column)) => Some(column.clone()),
+            _ => return Err(RelaxError::Parse("expected a column to aggregate".to_string()).at(tokens.span())),
+        };
+        if tokens.next() != Some(&Token::CloseParen) {
+            return Err(RelaxError::Parse("expected ')' after aggregated column".to_string()).at(tokens.span()));
+        }
+        if tokens.next() != Some(&Token::Arrow) {
+            return Err(RelaxError::Parse("expected '->' and a name for the aggregate".to_string()).at(tokens.span()));
+        }
+        let Some(Token::Symbol(name)) = tokens.next() else {
+            return Err(RelaxError::Parse("expected a name after '->'".to_string()).at(tokens.span()));
+        };
+        result.push(Aggregate { function, column, name: name.clone() });
+
+        if tokens.peek() != Some(&Token::Comma) {
+            return Ok(result);
+        }
+        tokens.next();
+    }
+}
+
+/// Parses a comma separated list of columns, each optionally followed by `asc` or `desc`.
+fn sort_keys(tokens: &mut TokenStream) -> Result<Vec<SortKey>, RelaxError> {
+    let mut result = vec![];
+    loop {
+        let Some(Token::Symbol(column)) = tokens.next() else {
+            return Err(RelaxError::Parse("expected a column to sort on".to_string()).at(tokens.span()));
+        };
+        let descending = match tokens.peek() {
+            Some(Token::Symbol(order)) if order.eq_ignore_ascii_case("asc") || order.eq_ignore_ascii_case("desc") => {
+                tokens.next();
+                order.eq_ignore_ascii_case("desc")
+            },
+            _ => false,
+        };
+        result.push(SortKey { column: column.clone(), descending });
+
+        if tokens.peek() != Some(&Token::Comma) {
+            return Ok(result);
+        }
+        tokens.next();
+    }
+}
+
+/// Consumes a `<-`. It is lexed as `<` and `-`, so that `a<-1` in a
+/// condition still compares `a` with `-1`.
+fn left_arrow(tokens: &mut TokenStream) -> bool {
+    if (tokens.peek(), tokens.peek_nth(1)) != (Some(&Token::Lesser), Some(&Token::Minus)) {
+        return false;
+    }
+    tokens.next();
+    tokens.next();
+    true
+}
+
+/// Parses either a relation name or a list of `new<-old` column renames.
+fn rename(tokens: &mut TokenStream) -> Result<Rename, RelaxError> {
+    let Some(Token::Symbol(name)) = tokens.next() else {
+        return Err(RelaxError::Parse("expected a name after rename".to_string()).at(tokens.span()));
+    };
+    if (tokens.peek(), tokens.peek_nth(1)) != (Some(&Token::Lesser), Some(&Token::Minus)) {
+        return Ok(Rename::Relation(name.clone()));
+    }
+
+    let mut columns = vec![];
+    let mut new_name = name.clone();
+    loop {
+        if !left_arrow(tokens) {
+            return Err(RelaxError::Parse(format!("expected '<-' after '{new_name}'")).at(tokens.peek_span()));
+        }
+        let Some(Token::Symbol(old_name)) = tokens.next() else {
+            return Err(RelaxError::Parse("expected a column name after '<-'".to_string()).at(tokens.span()));
+        };
+        columns.push((new_name, old_name.clone()));
+
+        if tokens.peek() != Some(&Token::Comma) {
+            break;
+        }
+        tokens.next();
+        let Some(Token::Symbol(name)) = tokens.next() else {
+            return Err(RelaxError::Parse("expected a column name after ','".to_string()).at(tokens.span()));
+        };
+        new_name = name.clone();
+    }
+
+    Ok(Rename::Columns(columns))
+}
+
+/// A single statement of a REPL line or script.
+#[derive (Debug)]
+pub enum Statement {
+    /// An expression whose result is printed.
+    Query(Box<Expr>),
+    /// `Name = { ... }` creates a table from the rows of a table literal.
+    Create { name: String, rows: Vec<Vec<SpannedToken>>, span: Span },
+    /// `Name = <expr>` stores the result of an expression as a table.
+    Assign { name: String, expr: Box<Expr> },
+    /// `view Name := <expr>` stores the expression itself, to be evaluated
+    /// whenever the view is used.
+    View { name: String, expr: Box<Expr>, span: Span },
+    /// `load Name from 'file.csv'` reads a table from a CSV file.
+    Load { name: String, path: String, span: Span },
+    /// `save <expr> to 'file.csv'` writes the result of an expression to a CSV file.
+    Save { expr: Box<Expr>, path: String, span: Span },
+}
+
+/// The statements of a REPL line or script, separated by `;` or line
+/// breaks. They are parsed one at a time, so each can run before a later
+/// one turns out to be malformed. Gamma's `;` is consumed along with its
+/// arguments, so a `;` left over after a statement always ends it.
+pub struct Statements<'a> {
+    tokens: TokenStream<'a>,
+    failed: bool,
+}
+
+pub fn statements(tokens: &[SpannedToken]) -> Statements<'_> {
+    Statements { tokens: TokenStream::new(tokens), failed: false }
+}
+
+impl Statements<'_> {
+    /// Whether the last statement ran up to the end of the input.
+    pub fn at_end(&self) -> bool {
+        matches!(self.tokens.peek(), Some(Token::Eof) | None)
+    }
+
+    /// The span of the last token of the last statement.
+    pub fn span(&self) -> Span {
+        self.tokens.span()
+    }
+}
+
+impl Iterator for Statements<'_> {
+    type Item = Result<Statement, RelaxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        while self.tokens.peek() == Some(&Token::Semicolon) {
+            self.tokens.next();
+        }
+        if self.at_end() {
+            return None;
+        }
+        let result = statement(&mut self.tokens).and_then( |statement| match self.tokens.peek() {
+            Some(Token::Semicolon | Token::Eof) | None => Ok(statement),
+            // Without a `;`, a statement that can't continue ends at a line break
+            Some(_) if self.tokens.peek_span().line > self.tokens.span().line => Ok(statement),
+            Some(token) => Err(RelaxError::Parse(format!("unexpected {token:?} after statement")).at(self.tokens.peek_span())),
+        });
+        self.failed = result.is_err();
+        Some(result)
+    }
 }
 
-pub fn parse(tokens: &Vec<Token>) -> Box<Expr> {
-    let mut iter = tokens.iter().peekable();
-    expr(&mut iter)
+fn statement(tokens: &mut TokenStream) -> Result<Statement, RelaxError> {
+    // `load`, `from`, `save`, `to` and `view` are only keywords here, so
+    // they can still be used as names elsewhere
+    let is_symbol = |token: Option<&Token>, word: &str| matches!(token, Some(Token::Symbol(symbol)) if symbol == word);
+    if is_symbol(tokens.peek(), "load") && is_symbol(tokens.peek_nth(2), "from") {
+        let start = tokens.peek_span();
+        tokens.next();
+        let Some(Token::Symbol(name)) = tokens.next() else { unreachable!() };
+        tokens.next();
+        let path = file_path(tokens)?;
+        return Ok(Statement::Load { name: name.clone(), path, span: start.to(tokens.span()) });
+    }
+    if is_symbol(tokens.peek(), "save") && !matches!(tokens.peek_nth(1), Some(Token::Equals | Token::Semicolon | Token::Eof) | None) {
+        let start = tokens.peek_span();
+        tokens.next();
+        let expr = expr(tokens)?;
+        if !is_symbol(tokens.next(), "to") {
+            return Err(RelaxError::Parse("expected to after the relation to save".to_string()).at(tokens.span()));
+        }
+        let path = file_path(tokens)?;
+        return Ok(Statement::Save { expr, path, span: start.to(tokens.span()) });
+    }
+    if is_symbol(tokens.peek(), "view") && tokens.peek_nth(2) == Some(&Token::Define) {
+        let start = tokens.peek_span();
+        tokens.next();
+        let Some(Token::Symbol(name)) = tokens.next() else {
+            return Err(RelaxError::Parse("expected a name after view".to_string()).at(tokens.span()));
+        };
+        tokens.next();
+        let expr = expr(tokens)?;
+        return Ok(Statement::View { name: name.clone(), expr, span: start.to(tokens.span()) });
+    }
+    let (Some(Token::Symbol(name)), Some(Token::Equals)) = (tokens.peek(), tokens.peek_nth(1)) else {
+        return Ok(Statement::Query(expr(tokens)?));
+    };
+    let start = tokens.peek_span();
+    tokens.next();
+    tokens.next();
+    if tokens.peek() == Some(&Token::OpenCurly) {
+        let rows = table_rows(tokens)?;
+        return Ok(Statement::Create { name: name.clone(), rows, span: start.to(tokens.span()) });
+    }
+    Ok(Statement::Assign { name: name.clone(), expr: expr(tokens)? })
+}
+
+fn file_path(tokens: &mut TokenStream) -> Result<String, RelaxError> {
+    match tokens.next() {
+        Some(Token::String(path)) => Ok(path.clone()),
+        _ => Err(RelaxError::Parse("expected a file name in quotes".to_string()).at(tokens.span())),
+    }
+}
+
+/// Parses a table literal like `{ a, b  1, 2  3, 4 }` into rows of value
+/// tokens, the first holding the headers. A value that isn't followed by a
+/// comma ends its row.
+fn table_rows(tokens: &mut TokenStream) -> Result<Vec<Vec<SpannedToken>>, RelaxError> {
+    let mut result: Vec<Vec<SpannedToken>> = Vec::new();
+    let mut row: Vec<SpannedToken> = Vec::new();
+    let start = tokens.peek_span();
+    tokens.next();
+
+    while let Some(token) = tokens.next() {
+        let spanned = match token {
+            Token::CloseCurly => return Ok(result),
+            Token::Comma => continue,
+            Token::Symbol(_) | Token::Number(_) | Token::String(_) | Token::Boolean(_) | Token::Null => {
+                SpannedToken { token: token.clone(), span: tokens.span() }
+            },
+            // A sign in front of a number belongs to the literal
+            Token::Minus | Token::Plus if matches!(tokens.peek(), Some(Token::Number(_))) => {
+                let span = tokens.span();
+                let Some(Token::Number(text)) = tokens.next() else { unreachable!() };
+                let sign = if *token == Token::Minus { "-" } else { "" };
+                SpannedToken { token: Token::Number(format!("{sign}{text}")), span: span.to(tokens.span()) }
+            },
+            Token::Eof => break,
+            token => return Err(RelaxError::Parse(format!("unable to parse token {token:?} while processing table")).at(tokens.span())),
+        };
+        row.push(spanned);
+        if tokens.peek() != Some(&Token::Comma) {
+            result.push(std::mem::take(&mut row));
+        }
+    }
+    Err(RelaxError::Parse("expected '}' at end of table".to_string()).at(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenizer::get_tokens;
+
+    fn parse(source: &str) -> Vec<Statement> {
+        let tokens = get_tokens(source).unwrap();
+        statements(&tokens).collect::<Result<Vec<Statement>, RelaxError>>().unwrap()
+    }
+
+    #[test]
+    fn join_ends_at_a_line_break() {
+        let statements = parse("R join S\nR");
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(&statements[0], Statement::Query(expr) if matches!(**expr, Expr::Binary { operator: Token::NaturalJoin, .. })));
+        assert!(matches!(&statements[1], Statement::Query(expr) if matches!(**expr, Expr::Literal(..))));
+    }
+
+    #[test]
+    fn join_operand_on_the_next_line() {
+        let statements = parse("E join E.dept = D.id\nD\nE");
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(&statements[0], Statement::Query(expr) if matches!(**expr, Expr::BinaryCond { operator: Token::Join, .. })));
+    }
+
+    #[test]
+    fn join_condition_on_one_line() {
+        let statements = parse("R join a = b S\nR");
+        assert_eq!(statements.len(), 2);
+        assert!(matches!(&statements[0], Statement::Query(expr) if matches!(**expr, Expr::BinaryCond { operator: Token::Join, .. })));
+    }
+
+    #[test]
+    fn left_arrow_only_renames() {
+        let statements = parse("select a<-1 R\nrename b<-a R");
+        assert!(matches!(&statements[0], Statement::Query(expr) if matches!(**expr, Expr::UnaryCond { .. })));
+        let Statement::Query(expr) = &statements[1] else { panic!("expected a query") };
+        let Expr::UnaryRename { rename, .. } = expr.as_ref() else { panic!("expected a rename") };
+        assert!(matches!(rename.as_ref(), Rename::Columns(columns) if columns == &[("b".to_string(), "a".to_string())]));
+    }
+
+    /// Defines each `view Name := expr` in `source` in turn, as the REPL
+    /// would, stopping at the first one that is rejected.
+    fn define_views(source: &str) -> Result<HashMap<String, Relation>, RelaxError> {
+        let mut relations = HashMap::new();
+        for statement in parse(source) {
+            let Statement::View { name, expr, .. } = statement else { panic!("expected a view") };
+            check_view(&name, &expr, &relations)?;
+            relations.insert(name, Relation::View(expr));
+        }
+        Ok(relations)
+    }
+
+    #[test]
+    fn views_cannot_depend_on_themselves() {
+        assert!(define_views("view A := A").is_err());
+        assert!(define_views("view A := select x = 1 (A union B)").is_err());
+        assert!(define_views("view A := B\nview B := A").is_err());
+    }
+
+    #[test]
+    fn views_are_redefined_through_a_chain() {
+        assert!(define_views("view A := B\nview B := C\nview C := A").is_err());
+        assert!(define_views("view A := B\nview B := C\nview A := C\nview C := D").is_ok());
+        // Redefining a view drops the old definition's dependencies
+        assert!(define_views("view A := B\nview A := C\nview B := A").is_ok());
+    }
+
+    #[test]
+    fn view_is_only_a_keyword_before_a_definition() {
+        let statements = parse("view = project a R\nview\nview V := view");
+        assert!(matches!(&statements[0], Statement::Assign { name, .. } if name == "view"));
+        assert!(matches!(&statements[1], Statement::Query(expr) if matches!(**expr, Expr::Literal(Token::Symbol(ref name), _) if name == "view")));
+        assert!(matches!(&statements[2], Statement::View { name, .. } if name == "V"));
+    }
+
+    #[test]
+    fn sort_directions_ignore_case() {
+        let statements = parse("tau a ASC, b Desc E");
+        let Statement::Query(expr) = &statements[0] else { panic!("expected a query") };
+        let Expr::UnarySort { keys, .. } = expr.as_ref() else { panic!("expected a sort") };
+        assert_eq!(keys.iter().map( |key| key.descending).collect::<Vec<bool>>(), vec![false, true]);
+    }
+
+    #[test]
+    fn statements_parse_one_at_a_time() {
+        let tokens = get_tokens("R; project ( R; S").unwrap();
+        let mut statements = statements(&tokens);
+        assert!(matches!(statements.next(), Some(Ok(Statement::Query(_)))));
+        assert!(matches!(statements.next(), Some(Err(_))));
+        assert!(statements.next().is_none());
+    }
 }
@@ -1,66 +1,118 @@
 use std::fmt::{Display, Formatter, Error};
-use crate::{condition::Condition, parser::List};
+use crate::{condition::Condition, parser::{Aggregate, AggregateFunction, List, ProjectColumn, SortKey}};
 use std::collections::HashMap;
 use std::iter::zip;
+use crate::value::{Decimal, Type, Value};
+use std::cmp::Ordering;
+use crate::error::RelaxError;
+use crate::tokenizer::Token;
 use itertools::Itertools;
 
 #[derive (Debug, Clone)]
 pub struct Table {
-    pub rows: Vec<Vec<String>>,
-    pub types: Vec<String>,
+    pub headers: Vec<String>,
+    pub types: Vec<Type>,
+    /// The relation each column belongs to, if it has been named.
+    pub relations: Vec<Option<String>>,
+    pub rows: Vec<Vec<Value>>,
 }
 
 impl Table {
-    pub fn new(headers: Vec<String>) -> Table {
-        Table { rows: vec![headers.clone()],
-            types: Vec::new(),
+    pub fn new(headers: Vec<String>, types: Vec<Type>) -> Table {
+        Table {
+            relations: vec![None; headers.len()],
+            headers,
+            types,
+            rows: Vec::new(),
         }
     }
 
-    pub fn union(&self, other: &Table) -> Result<Table, &'static str> {
-        if self.rows[0] != other.rows[0] {
-            return Err("error: tables must have same columns to union");
+    fn empty_like(&self) -> Table {
+        Table {
+            headers: self.headers.clone(),
+            types: self.types.clone(),
+            relations: self.relations.clone(),
+            rows: Vec::new(),
         }
+    }
 
+    /// Renames the relation every column of this table belongs to.
+    pub fn rename_relation(&self, name: &str) -> Table {
         let mut result = self.clone();
-        for row in other.rows.iter().skip(1) {
-            if !result.rows.contains(row) {
-                result.rows.push(row.clone());
-            }
+        result.relations = vec![Some(name.to_string()); self.headers.len()];
+        result
+    }
+
+    /// Renames columns, given as `(new_name, old_name)` pairs.
+    pub fn rename_columns(&self, renames: &[(String, String)]) -> Result<Table, RelaxError> {
+        let mut result = self.clone();
+        for (new_name, old_name) in renames {
+            let index = self.column_index(old_name)?;
+            result.headers[index] = new_name.clone();
         }
         Ok(result)
     }
 
-    pub fn minus(&self, other: &Table) -> Result<Table, &'static str> {
-        if self.rows[0] != other.rows[0] {
-            return Err("error: tables must have same columns to minus");
+    /// Checks that two tables can be combined by a set operation, and
+    /// returns them with any column that is Integer on one side and Decimal
+    /// on the other widened to Decimal, so their rows compare the way their
+    /// values do.
+    fn set_compatible(&self, other: &Table, operation: &str) -> Result<(Table, Table), RelaxError> {
+        if self.headers != other.headers {
+            return Err(RelaxError::Schema(format!("tables must have same columns to {operation}")));
         }
+        let mut left = self.clone();
+        let mut right = other.clone();
+        for (i, header) in self.headers.iter().enumerate() {
+            match (self.types[i], other.types[i]) {
+                (left_type, right_type) if left_type == right_type => {},
+                (Type::Integer, Type::Decimal) => left.widen_to_decimal(i),
+                (Type::Decimal, Type::Integer) => right.widen_to_decimal(i),
+                (left_type, right_type) => return Err(RelaxError::Schema(
+                    format!("cannot {operation} column '{header}' of type {left_type} with one of type {right_type}"))),
+            }
+        }
+        Ok((left, right))
+    }
 
-        let mut result = Table {
-            rows: vec![self.rows[0].clone()],
-            types: self.types.clone(),
-        };
+    /// Turns an Integer column into a Decimal one.
+    fn widen_to_decimal(&mut self, column: usize) {
+        self.types[column] = Type::Decimal;
+        for row in self.rows.iter_mut() {
+            if let Value::Integer(value) = row[column] {
+                row[column] = Value::Decimal(Decimal::from(value));
+            }
+        }
+    }
 
-        for row in self.rows.iter().skip(1) {
-            if !other.rows.contains(row) {
+    pub fn union(&self, other: &Table) -> Result<Table, RelaxError> {
+        let (mut result, other) = self.set_compatible(other, "union")?;
+        for row in other.rows.iter() {
+            if !result.rows.contains(row) {
                 result.rows.push(row.clone());
             }
         }
         Ok(result)
     }
 
-    pub fn intersect(&self, other: &Table) -> Result<Table, &'static str> {
-        if self.rows[0] != other.rows[0] {
-           return Err("error: tables must have same columns to intersect");
+    pub fn minus(&self, other: &Table) -> Result<Table, RelaxError> {
+        let (left, other) = self.set_compatible(other, "minus")?;
+        let mut result = left.empty_like();
+
+        for row in left.rows.iter() {
+            if !other.rows.contains(row) {
+                result.rows.push(row.clone());
+            }
         }
+        Ok(result)
+    }
 
-        let mut result = Table {
-            rows: vec![self.rows[0].clone()],
-            types: self.types.clone(),
-        };
+    pub fn intersect(&self, other: &Table) -> Result<Table, RelaxError> {
+        let (left, other) = self.set_compatible(other, "intersect")?;
+        let mut result = left.empty_like();
 
-        for self_row in self.rows.iter().skip(1) {
-            for other_row in other.rows.iter().skip(1) {
+        for self_row in left.rows.iter() {
+            for other_row in other.rows.iter() {
                 if self_row == other_row {
                     result.rows.push(self_row.clone());
                 }
@@ -70,16 +122,14 @@ impl Table {
         Ok(result)
     }
 
-    pub fn multiply(&self, other: &Table) -> Result<Table, &'static str> {
-        let mut result = Table {
-            rows: vec![self.rows[0].clone()],
-            types: self.types.clone(),
-        };
+    pub fn multiply(&self, other: &Table) -> Result<Table, RelaxError> {
+        let mut result = self.empty_like();
+        result.headers.extend(other.headers.clone());
         result.types.extend(other.types.clone());
-        result.rows[0].extend(other.rows[0].clone());
+        result.relations.extend(other.relations.clone());
 
-        for self_row in self.rows.iter().skip(1) {
-            for other_row in other.rows.iter().skip(1) {
+        for self_row in self.rows.iter() {
+            for other_row in other.rows.iter() {
                 let mut new_row = self_row.clone();
                 new_row.extend(other_row.clone());
                 result.rows.push(new_row);
@@ -89,24 +139,19 @@ impl Table {
         Ok(result)
     }
 
-    pub fn divide(&self, other: &Table) -> Result<Table, &'static str> {
-        let attributes: List = self.rows[0].clone().into_iter().filter( |elem| !other.rows[0].contains(elem)).collect();
-        let result = self.project(attributes.clone()).unwrap().multiply(other);
-        let result = result.unwrap().minus(self);
-        let result = result.unwrap().project(attributes.clone());
-        let result = self.project(attributes).unwrap().minus(&result.unwrap());
-
-        result
+    pub fn divide(&self, other: &Table) -> Result<Table, RelaxError> {
+        let attributes: List = self.headers.clone().into_iter().filter( |elem| !other.headers.contains(elem)).collect();
+        let result = self.project(attributes.clone())?.multiply(other)?;
+        let result = result.minus(self)?;
+        let result = result.project(attributes.clone())?;
+        self.project(attributes)?.minus(&result)
     }
 
-    pub fn select(&self, condition: &Box<Condition>) -> Result<Table, &'static str> {
-        let mut result = Table {
-            rows: vec![self.rows[0].clone()],
-            types: self.types.clone(),
-        };
+    pub fn select(&self, condition: &Condition) -> Result<Table, RelaxError> {
+        let mut result = self.empty_like();
 
-        for self_row in self.rows.iter().skip(1) {
-            if Self::row_meets_condition(&self.rows[0], &self_row, &condition) {
+        for self_row in self.rows.iter() {
+            if self.row_meets_condition(self_row, condition)? {
                 result.rows.push(self_row.clone());
             }
         }
@@ -114,98 +159,469 @@ impl Table {
         Ok(result)
     }
 
-    fn row_meets_condition(header: &Vec<String>, row: &Vec<String>, condition: &Condition) -> bool {
-        let mut row_lookup = HashMap::new();
-        for (key, value) in zip(header.clone(), row.clone()) {
-            row_lookup.insert(key, value); 
+    fn row_meets_condition(&self, row: &[Value], condition: &Condition) -> Result<bool, RelaxError> {
+        // Rows where the condition is unknown are dropped along with false ones
+        match condition.eval(&Row { table: self, values: row })? {
+            Value::Boolean(result) => Ok(result),
+            Value::Null => Ok(false),
+            value => Err(RelaxError::Type(format!("condition must evaluate to a Boolean, found '{value}'"))),
         }
+    }
 
-        condition.eval(&row_lookup).parse().unwrap()
+    /// The headers as they are shown, qualified with their relation when
+    /// the name alone is ambiguous.
+    pub fn qualified_headers(&self) -> Vec<String> {
+        self.headers.iter().zip(self.relations.iter()).map( |(header, relation)| {
+            match relation {
+                Some(relation) if self.headers.iter().filter( |other| *other == header).count() > 1 => format!("{relation}.{header}"),
+                _ => header.clone(),
+            }
+        }).collect()
     }
 
-    pub fn project(&self, columns: List) -> Result<Table, &'static str> {
-        let mut result = self.clone();
-        let mut columns = columns.clone();
-
-        for column in self.rows[0].iter() {
-            if !columns.contains(&column) { 
-                result = result.remove_column(column);
-            } else {
-                if let Some(index) = columns.iter().position( |elem| elem == column) {
-                    columns.remove(index);
+    /// Finds the column referred to by `name`, which is either a plain
+    /// column name or qualified with its relation as `relation.column`.
+    pub fn column_index(&self, name: &str) -> Result<usize, RelaxError> {
+        let matches: Vec<usize> = match name.split_once('.') {
+            Some((relation, column)) => (0..self.headers.len())
+                .filter( |&i| self.headers[i] == column && self.relations[i].as_deref() == Some(relation))
+                .collect(),
+            None => (0..self.headers.len())
+                .filter( |&i| self.headers[i] == name)
+                .collect(),
+        };
+        match matches[..] {
+            [index] => Ok(index),
+            [] => Err(RelaxError::UnknownColumn(name.to_string())),
+            _ => Err(RelaxError::AmbiguousColumn(name.to_string())),
+        }
+    }
+
+    pub fn project(&self, columns: List) -> Result<Table, RelaxError> {
+        let mut indices = columns.iter()
+            .map( |column| self.column_index(column))
+            .collect::<Result<Vec<usize>, RelaxError>>()?;
+        indices.sort();
+        indices.dedup();
+
+        let mut result = self.keep_columns(&indices);
+        result.rows = result.rows.into_iter().unique().collect();
+
+        Ok(result)
+    }
+
+    /// Projects onto a list of columns and computed expressions, in list order.
+    pub fn extended_project(&self, columns: &[ProjectColumn]) -> Result<Table, RelaxError> {
+        let mut result = Table::new(vec![], vec![]);
+        for column in columns {
+            let (header, relation) = match (column.expression.as_ref(), &column.name) {
+                (Condition::Literal(Token::Symbol(key), span), name) => {
+                    let index = self.column_index(key).map_err( |error| error.at(*span))?;
+                    (name.clone().unwrap_or(self.headers[index].clone()), self.relations[index].clone())
+                },
+                (_, Some(name)) => (name.clone(), None),
+                (expression, None) => return Err(RelaxError::Parse("computed columns must be named".to_string()).at(expression.span())),
+            };
+            result.headers.push(header);
+            result.relations.push(relation);
+        }
+
+        for row in self.rows.iter() {
+            let row = Row { table: self, values: row };
+            result.rows.push(columns.iter()
+                .map( |column| column.expression.eval(&row))
+                .collect::<Result<Vec<Value>, RelaxError>>()?);
+        }
+
+        // Computed columns take the type of their values, with Integers
+        // promoted when a column also holds Decimals
+        for i in 0..result.headers.len() {
+            let mut ty = None;
+            for value in result.rows.iter().filter_map( |row| row[i].ty()) {
+                ty = match (ty, value) {
+                    (None, value) => Some(value),
+                    (Some(Type::Integer), Type::Decimal) | (Some(Type::Decimal), Type::Integer) => Some(Type::Decimal),
+                    (Some(ty), value) if ty == value => Some(ty),
+                    (Some(ty), value) => return Err(RelaxError::Type(format!("column '{}' mixes {ty} and {value} values", result.headers[i]))),
+                };
+            }
+            let ty = match (ty, columns[i].expression.as_ref()) {
+                (_, Condition::Literal(Token::Symbol(key), _)) => self.types[self.column_index(key)?],
+                (Some(ty), _) => ty,
+                (None, expression) => expression.static_type().unwrap_or(Type::String),
+            };
+            if ty == Type::Decimal {
+                for row in result.rows.iter_mut() {
+                    if let Value::Integer(value) = row[i] {
+                        row[i] = Value::Decimal(Decimal::from(value));
+                    }
                 }
             }
+            result.types.push(ty);
         }
 
         result.rows = result.rows.into_iter().unique().collect();
+        Ok(result)
+    }
+
+    fn keep_columns(&self, indices: &[usize]) -> Table {
+        Table {
+            headers: indices.iter().map( |&i| self.headers[i].clone()).collect(),
+            types: indices.iter().map( |&i| self.types[i]).collect(),
+            relations: indices.iter().map( |&i| self.relations[i].clone()).collect(),
+            rows: self.rows.iter()
+                .map( |row| indices.iter().map( |&i| row[i].clone()).collect())
+                .collect(),
+        }
+    }
+
+    /// Groups rows on the `group` columns and produces one row per group,
+    /// holding the group's values followed by each aggregate over it.
+    pub fn group_by(&self, group: &List, aggregates: &[Aggregate]) -> Result<Table, RelaxError> {
+        let group_indices = group.iter()
+            .map( |column| self.column_index(column))
+            .collect::<Result<Vec<usize>, RelaxError>>()?;
+        let aggregate_indices = aggregates.iter()
+            .map( |aggregate| aggregate.column.as_ref().map( |column| self.column_index(column)).transpose())
+            .collect::<Result<Vec<Option<usize>>, RelaxError>>()?;
+
+        let keys = self.keep_columns(&group_indices);
+        let mut result = Table { rows: Vec::new(), ..keys.clone() };
+        for (aggregate, index) in zip(aggregates, &aggregate_indices) {
+            result.headers.push(aggregate.name.clone());
+            result.types.push(aggregate_type(aggregate, index.map( |i| self.types[i]))?);
+            result.relations.push(None);
+        }
+
+        // Groups in order of first appearance; without grouping columns the
+        // whole table is a single group, even when it is empty.
+        let no_key = Vec::new();
+        let mut groups: Vec<(&Vec<Value>, Vec<&Vec<Value>>)> = Vec::new();
+        let mut group_lookup: HashMap<&Vec<Value>, usize> = HashMap::new();
+        if group_indices.is_empty() {
+            groups.push((&no_key, vec![]));
+            group_lookup.insert(&no_key, 0);
+        }
+        for (key, row) in zip(keys.rows.iter(), self.rows.iter()) {
+            let index = *group_lookup.entry(key).or_insert_with( || {
+                groups.push((key, vec![]));
+                groups.len() - 1
+            });
+            groups[index].1.push(row);
+        }
+
+        for (key, rows) in groups {
+            let mut new_row = key.clone();
+            for (aggregate, index) in zip(aggregates, &aggregate_indices) {
+                new_row.push(aggregate_value(aggregate.function, *index, &rows)?);
+            }
+            result.rows.push(new_row);
+        }
 
         Ok(result)
     }
 
-    fn remove_column(&self, column: &str) -> Table {
-        let index = self.rows[0].iter().position( |col| col == column).unwrap();
+    /// Stable sort on each key in turn. `Null`s sort after every other
+    /// value, in either direction.
+    pub fn sort_by(&self, keys: &[SortKey]) -> Result<Table, RelaxError> {
+        let indices = keys.iter()
+            .map( |key| self.column_index(&key.column))
+            .collect::<Result<Vec<usize>, RelaxError>>()?;
 
         let mut result = self.clone();
-        result.rows = result.rows
-            .into_iter().map(|row| {
-                row.into_iter()
-                    .enumerate()
-                    .filter( |&(i, _)| i != index)
-                    .map( |(_, elem)| elem)
-                    .collect::<Vec<String>>()
-            })
+        let mut error = None;
+        result.rows.sort_by( |left, right| {
+            for (key, &index) in zip(keys, &indices) {
+                // Only the order of other values is reversed by desc
+                let ordering = match (&left[index], &right[index]) {
+                    (Value::Null, Value::Null) => Ordering::Equal,
+                    (Value::Null, _) => Ordering::Greater,
+                    (_, Value::Null) => Ordering::Less,
+                    (left, right) => match left.compare(right) {
+                        Ok(ordering) if key.descending => ordering.unwrap_or(Ordering::Equal).reverse(),
+                        Ok(ordering) => ordering.unwrap_or(Ordering::Equal),
+                        Err(err) => {
+                            error.get_or_insert(err);
+                            Ordering::Equal
+                        },
+                    },
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    }
+
+    /// Pairs of column indices in `self` and `other` that share a name.
+    fn shared_columns(&self, other: &Table) -> Result<Vec<(usize, usize)>, RelaxError> {
+        self.headers.iter()
+            .unique()
+            .filter( |header| other.headers.contains(header))
+            .map( |header| Ok((self.column_index(header)?, other.column_index(header)?)))
+            .collect()
+    }
+
+    /// Whether two rows agree on every shared column. `Null`s never match.
+    fn rows_match(row: &[Value], other_row: &[Value], shared: &[(usize, usize)]) -> Result<bool, RelaxError> {
+        for &(i, j) in shared {
+            if row[i].compare(&other_row[j])? != Some(Ordering::Equal) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Joins rows that agree on all columns with the same name, keeping one
+    /// copy of each shared column. Without shared columns this is a product.
+    pub fn natural_join(&self, other: &Table) -> Result<Table, RelaxError> {
+        let shared = self.shared_columns(other)?;
+        let other_columns: Vec<usize> = (0..other.headers.len())
+            .filter( |j| !shared.iter().any( |(_, shared_j)| shared_j == j))
             .collect();
-        result.types.remove(index);
+        let mut result = self.empty_like().multiply(&other.keep_columns(&other_columns))?;
+
+        for self_row in self.rows.iter() {
+            for other_row in other.rows.iter() {
+                if Self::rows_match(self_row, other_row, &shared)? {
+                    let mut new_row = self_row.clone();
+                    new_row.extend(other_columns.iter().map( |&j| other_row[j].clone()));
+                    result.rows.push(new_row);
+                }
+            }
+        }
 
-        result 
+        Ok(result)
     }
 
-    pub fn join(&self, condition: &Box<Condition>, other: &Table) -> Result<Table, &'static str> {
-       self.multiply(other).unwrap().select(condition)
+    /// The rows of `self` that match at least one row of `other` on their shared columns.
+    pub fn left_semi_join(&self, other: &Table) -> Result<Table, RelaxError> {
+        self.filter_matching(other, true)
     }
 
-    pub fn left_join(&self, condition: &Box<Condition>, other: &Table) -> Result<Table, &'static str> {
-        let attributes: List = self.rows[0].clone().into_iter().collect();
-        let mut null_row = Table {
-            rows: vec![other.rows[0].clone().into_iter().filter( |elem| !self.rows[0].contains(elem)).collect()],
-            types: other.types.clone() 
-        };
-        null_row.rows.push(null_row.rows[0].clone().into_iter().map( |_| "Null".to_string()).collect());
-        self.join(condition, other).unwrap()
-            .union(&self.minus(&self.join(condition, other).unwrap().project(attributes).unwrap()).unwrap()
-            .multiply(&null_row).unwrap())
+    /// The rows of `self` that match no row of `other` on their shared columns.
+    pub fn anti_join(&self, other: &Table) -> Result<Table, RelaxError> {
+        self.filter_matching(other, false)
     }
 
-    pub fn right_join(&self, condition: &Box<Condition>, other: &Table) -> Result<Table, &'static str> {
-        let attributes: List = other.rows[0].clone().into_iter().collect();
-        let mut null_row = Table {
-            rows: vec![self.rows[0].clone().into_iter().filter( |elem| !other.rows[0].contains(elem)).collect()],
-            types: self.types.clone() 
-        };
-        null_row.rows.push(null_row.rows[0].clone().into_iter().map( |_| "Null".to_string()).collect());
-        self.join(condition, other).unwrap()
-            .union(&null_row
-            .multiply(&other.minus(&self.join(condition, other).unwrap().project(attributes).unwrap()).unwrap()).unwrap())
+    fn filter_matching(&self, other: &Table, keep_matching: bool) -> Result<Table, RelaxError> {
+        let shared = self.shared_columns(other)?;
+        let mut result = self.empty_like();
+        for self_row in self.rows.iter() {
+            let mut matched = false;
+            for other_row in other.rows.iter() {
+                if Self::rows_match(self_row, other_row, &shared)? {
+                    matched = true;
+                    break;
+                }
+            }
+            if matched == keep_matching {
+                result.rows.push(self_row.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn join(&self, condition: &Condition, other: &Table) -> Result<Table, RelaxError> {
+       self.multiply(other)?.select(condition)
+    }
+
+    pub fn left_join(&self, condition: &Condition, other: &Table) -> Result<Table, RelaxError> {
+        let mut result = self.join(condition, other)?;
+        let width = self.headers.len();
+        for row in self.rows.iter() {
+            if !result.rows.iter().any( |joined| joined[..width] == row[..]) {
+                let mut new_row = row.clone();
+                new_row.extend(vec![Value::Null; other.headers.len()]);
+                result.rows.push(new_row);
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn right_join(&self, condition: &Condition, other: &Table) -> Result<Table, RelaxError> {
+        let mut result = self.join(condition, other)?;
+        let width = self.headers.len();
+        for row in other.rows.iter() {
+            if !result.rows.iter().any( |joined| joined[width..] == row[..]) {
+                let mut new_row = vec![Value::Null; width];
+                new_row.extend(row.clone());
+                result.rows.push(new_row);
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn full_join(&self, condition: &Condition, other: &Table) -> Result<Table, RelaxError> {
+        self.left_join(condition, other)?.union(&self.right_join(condition, other)?)
     }
+}
+
+fn aggregate_type(aggregate: &Aggregate, column_type: Option<Type>) -> Result<Type, RelaxError> {
+    match (aggregate.function, column_type) {
+        (AggregateFunction::Count, _) => Ok(Type::Integer),
+        (AggregateFunction::Sum, Some(ty @ (Type::Integer | Type::Decimal))) => Ok(ty),
+        (AggregateFunction::Avg, Some(Type::Integer | Type::Decimal)) => Ok(Type::Decimal),
+        (AggregateFunction::Min | AggregateFunction::Max, Some(ty)) => Ok(ty),
+        (function, ty) => Err(RelaxError::Type(format!("cannot compute {} of {} column '{}'",
+            format!("{function:?}").to_lowercase(), ty.map_or("untyped".to_string(), |ty| ty.to_string()), aggregate.column.as_deref().unwrap_or("*")))),
+    }
+}
+
+/// Computes an aggregate over the non-`Null` values of column `index` in `rows`,
+/// or over the rows themselves for `count(*)`.
+fn aggregate_value(function: AggregateFunction, index: Option<usize>, rows: &[&Vec<Value>]) -> Result<Value, RelaxError> {
+    let Some(index) = index else {
+        return Ok(Value::Integer(rows.len() as i64));
+    };
+    let values: Vec<&Value> = rows.iter().map( |row| &row[index]).filter( |value| **value != Value::Null).collect();
+
+    match function {
+        AggregateFunction::Count => Ok(Value::Integer(values.len() as i64)),
+        AggregateFunction::Sum | AggregateFunction::Avg if values.is_empty() => Ok(Value::Null),
+        AggregateFunction::Sum => values.into_iter().try_fold(Value::Integer(0), |sum, value| sum.add(value)),
+        AggregateFunction::Avg => {
+            let count = values.len() as i64;
+            let sum = values.into_iter().try_fold(Value::Integer(0), |sum, value| sum.add(value))?;
+            let average = sum.to_decimal().and_then( |sum| sum.checked_div(&Decimal::from(count)));
+            average.map(Value::Decimal).ok_or_else( || RelaxError::Arithmetic(format!("average of {sum} over {count} rows overflows")))
+        },
+        AggregateFunction::Min | AggregateFunction::Max => {
+            let mut result = Value::Null;
+            for value in values {
+                let ordering = value.compare(&result)?;
+                let better = match function {
+                    AggregateFunction::Min => Some(Ordering::Less),
+                    _ => Some(Ordering::Greater),
+                };
+                if result == Value::Null || ordering == better {
+                    result = value.clone();
+                }
+            }
+            Ok(result)
+        },
+    }
+}
 
-    pub fn full_join(&self, condition: &Box<Condition>, other: &Table) -> Result<Table, &'static str> {
-        self.left_join(condition, other).unwrap().union(&self.right_join(condition, other).unwrap())
+/// A single row of a table, used to look up column values while evaluating a condition.
+pub struct Row<'a> {
+    pub table: &'a Table,
+    pub values: &'a [Value],
+}
+
+impl Row<'_> {
+    pub fn get(&self, name: &str) -> Result<&Value, RelaxError> {
+        Ok(&self.values[self.table.column_index(name)?])
     }
 }
 
 impl Display for Table {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
-        let mut result = "".to_string();
-        self.rows.iter().for_each( |row| {
-            row.iter().for_each( |value| {
-                result.push_str(value.as_str());
-                result.push_str(", ");
-            });
-            result.pop();
-            result.pop();
-            result.push_str("\n");
-        });
+        writeln!(f, "{}", self.qualified_headers().join(", "))?;
+        for row in self.rows.iter() {
+            writeln!(f, "{}", row.iter().join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(ty: Type, values: Vec<Value>) -> Table {
+        let mut table = Table::new(vec!["a".to_string()], vec![ty]);
+        table.rows = values.into_iter().map( |value| vec![value]).collect();
+        table
+    }
+
+    fn select(table: &Table, condition: &str) -> Vec<Vec<Value>> {
+        let tokens = crate::tokenizer::get_tokens(condition).unwrap();
+        let condition = crate::condition::parse(&mut crate::tokenizer::TokenStream::new(&tokens)).unwrap();
+        table.select(&condition).unwrap().rows
+    }
+
+    #[test]
+    fn unknown_conditions_drop_rows() {
+        let table = column(Type::Integer, vec![Value::Integer(1), Value::Null]);
+        let known = vec![vec![Value::Integer(1)]];
+        assert_eq!(select(&table, "a > 0"), known);
+        // Null and false is false, so its negation keeps both rows
+        assert_eq!(select(&table, "not (a > 0 and false)").len(), 2);
+        // Null or true is true
+        assert_eq!(select(&table, "a > 0 or true").len(), 2);
+        // Not Null is still unknown, so neither row is kept
+        assert_eq!(select(&table, "not a > 0"), Vec::<Vec<Value>>::new());
+        assert_eq!(select(&table, "a = null or a <> null"), Vec::<Vec<Value>>::new());
+        assert_eq!(select(&table, "a is not null"), known);
+        assert_eq!(select(&table, "a is null"), vec![vec![Value::Null]]);
+    }
+
+    fn aggregate(function: AggregateFunction, column: Option<&str>) -> Aggregate {
+        Aggregate { function, column: column.map(String::from), name: format!("{function:?}") }
+    }
+
+    #[test]
+    fn aggregates_of_an_empty_table() {
+        let table = column(Type::Integer, vec![]);
+        let aggregates = [aggregate(AggregateFunction::Count, None), aggregate(AggregateFunction::Sum, Some("a")),
+            aggregate(AggregateFunction::Max, Some("a"))];
+        let result = table.group_by(&vec![], &aggregates).unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Integer(0), Value::Null, Value::Null]]);
+    }
+
+    #[test]
+    fn aggregates_skip_nulls() {
+        let mut table = Table::new(vec!["g".to_string(), "a".to_string()], vec![Type::Integer, Type::Decimal]);
+        table.rows = vec![
+            vec![Value::Integer(1), Value::Null],
+            vec![Value::Integer(1), Value::Null],
+            vec![Value::Integer(2), Value::Decimal(Decimal::from(3))],
+            vec![Value::Integer(2), Value::Null],
+        ];
+        let aggregates = [aggregate(AggregateFunction::Count, None), aggregate(AggregateFunction::Count, Some("a")),
+            aggregate(AggregateFunction::Sum, Some("a")), aggregate(AggregateFunction::Avg, Some("a"))];
+        let result = table.group_by(&vec!["g".to_string()], &aggregates).unwrap();
+        assert_eq!(result.types, vec![Type::Integer, Type::Integer, Type::Integer, Type::Decimal, Type::Decimal]);
+        assert_eq!(result.rows, vec![
+            vec![Value::Integer(1), Value::Integer(2), Value::Integer(0), Value::Null, Value::Null],
+            vec![Value::Integer(2), Value::Integer(2), Value::Integer(1), Value::Decimal(Decimal::from(3)), Value::Decimal(Decimal::from(3))],
+        ]);
+    }
+
+    #[test]
+    fn set_operations_widen_integers() {
+        let integers = column(Type::Integer, vec![Value::Integer(1), Value::Integer(2)]);
+        let decimals = column(Type::Decimal, vec![Value::Decimal(Decimal::from(1))]);
+
+        let union = integers.union(&decimals).unwrap();
+        assert_eq!(union.types, vec![Type::Decimal]);
+        assert_eq!(union.rows.len(), 2);
+        assert_eq!(integers.minus(&decimals).unwrap().rows, vec![vec![Value::Decimal(Decimal::from(2))]]);
+        assert_eq!(decimals.intersect(&integers).unwrap().rows.len(), 1);
+    }
+
+    #[test]
+    fn nulls_sort_last() {
+        let table = column(Type::Integer, vec![Value::Integer(1), Value::Null, Value::Integer(2)]);
+        for (descending, expected) in [(false, [Value::Integer(1), Value::Integer(2), Value::Null]),
+            (true, [Value::Integer(2), Value::Integer(1), Value::Null])] {
+            let sorted = table.sort_by(&[SortKey { column: "a".to_string(), descending }]).unwrap();
+            assert_eq!(sorted.rows.concat(), expected);
+        }
+    }
 
-        write!(f, "{result}")
+    #[test]
+    fn set_operations_reject_other_types() {
+        let integers = column(Type::Integer, vec![Value::Integer(1)]);
+        let strings = column(Type::String, vec![Value::String("x".to_string())]);
+        assert!(matches!(integers.union(&strings), Err(RelaxError::Schema(_))));
+        assert!(matches!(strings.minus(&integers), Err(RelaxError::Schema(_))));
     }
 }
@@ -1,11 +1,19 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+use crate::error::RelaxError;
+
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token {
     OpenCurly, CloseCurly,
     OpenParen,
     CloseParen,
+    OpenBracket,
+    CloseBracket,
 
     Equals,
+    NotEquals,
     Comma,
+    Semicolon,
 
     Plus,
     Divide,
@@ -14,18 +22,37 @@ pub enum Token {
     And,
     Or,
     Not,
+    Is,
+    Like,
+    Match,
+    In,
+    Between,
+    Case,
+    When,
+    Then,
+    Else,
+    End,
 
     Greater,
     GreaterEq,
     Lesser,
     LesserEq,
+    Arrow,
+    Define,
     
     Project,
     Select,
+    Rename,
+    Gamma,
+    Tau,
     Join,
     LeftJoin,
     RightJoin,
     FullJoin,
+    NaturalJoin,
+    LeftSemiJoin,
+    RightSemiJoin,
+    AntiJoin,
     Union,
     Intersect,
     Minus,
@@ -33,110 +60,408 @@ pub enum Token {
     Symbol(String),
     String(String),
     Number(String),
+    Boolean(bool),
+    Null,
 
-    EOF,
+    Eof,
 }
 
-pub fn get_tokens(chars: std::str::Chars) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut chars = chars.peekable();
-    
-    while let Some(c) = chars.next() {
-        match c {
-            '{' => tokens.push(Token::OpenCurly),
-            '}' => tokens.push(Token::CloseCurly),
-            '(' => tokens.push(Token::OpenParen),
-            ')' => tokens.push(Token::CloseParen),
-            '=' => tokens.push(Token::Equals),
-            ',' => tokens.push(Token::Comma),
-            '-' => tokens.push(Token::Minus),
-            '+' => tokens.push(Token::Plus),
-            '*' => tokens.push(Token::Multiply),
-            '/' => tokens.push(Token::Divide),
-            '>' => {
-                if let Some(next) = chars.peek()  {
-                   if *next == '=' {
+/// Where a token came from in the input: a byte range plus the 1-based
+/// line and column of its first character.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span { end: other.end.max(self.end), ..self }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// A cursor over spanned tokens, used by the expression and condition parsers.
+pub struct TokenStream<'a> {
+    tokens: &'a [SpannedToken],
+    position: usize,
+}
+
+impl<'a> TokenStream<'a> {
+    pub fn new(tokens: &'a [SpannedToken]) -> TokenStream<'a> {
+        TokenStream { tokens, position: 0 }
+    }
+
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position).map( |spanned| &spanned.token)
+    }
+
+    pub fn next(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        if token.is_some() {
+            self.position += 1;
+        }
+        token
+    }
+
+    /// The span of the next token, or of the last one at the end of input.
+    pub fn peek_span(&self) -> Span {
+        self.tokens.get(self.position)
+            .or(self.tokens.last())
+            .map_or(Span::default(), |spanned| spanned.span)
+    }
+
+    /// The token `n` places after the next one.
+    pub fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.position + n).map( |spanned| &spanned.token)
+    }
+
+    /// Where the cursor is, to come back to with `rewind`.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn rewind(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    /// The span of the most recently consumed token.
+    pub fn span(&self) -> Span {
+        self.tokens.get(self.position.saturating_sub(1))
+            .map_or(Span::default(), |spanned| spanned.span)
+    }
+}
+
+fn push_digits(chars: &mut Peekable<CharIndices>, word: &mut String) {
+    while let Some((_, c)) = chars.next_if( |(_, c)| c.is_ascii_digit()) {
+        word.push(c);
+    }
+}
+
+pub fn get_tokens(input: &str) -> Result<Vec<SpannedToken>, RelaxError> {
+    let mut tokens: Vec<SpannedToken> = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    let mut line = 1;
+    let mut line_start = 0;
+
+    while let Some((start, c)) = chars.next() {
+        let span = Span {
+            start,
+            end: start + c.len_utf8(),
+            line,
+            column: input[line_start..start].chars().count() + 1,
+        };
+        let token = match c {
+            '{' => Token::OpenCurly,
+            '}' => Token::CloseCurly,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '[' => Token::OpenBracket,
+            ']' => Token::CloseBracket,
+            '=' => Token::Equals,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            ':' => {
+                if chars.next_if( |(_, c)| *c == '=').is_none() {
+                    return Err(RelaxError::Lex("unexpected character ':', did you mean ':='?".to_string()).at(span));
+                }
+                Token::Define
+            },
+            '-' => {
+                match chars.peek() {
+                    Some((_, '>')) => {
                         chars.next();
-                        tokens.push(Token::GreaterEq);
+                        Token::Arrow
+                    },
+                    Some((_, '-')) => {
+                        // A comment runs to the end of the line
+                        while chars.next_if( |(_, c)| *c != '\n').is_some() {}
                         continue;
-                   }
-                } 
-                tokens.push(Token::Greater);
+                    },
+                    _ => Token::Minus,
+                }
+            },
+            '+' => Token::Plus,
+            '~' => Token::Match,
+            '*' => Token::Multiply,
+            '/' if chars.next_if( |(_, c)| *c == '*').is_some() => {
+                let mut terminated = false;
+                while let Some((i, c)) = chars.next() {
+                    if c == '*' && chars.next_if( |(_, c)| *c == '/').is_some() {
+                        terminated = true;
+                        break;
+                    }
+                    // Keep line numbers right for anything after the comment
+                    if c == '\n' {
+                        line += 1;
+                        line_start = i + 1;
+                    }
+                }
+                if !terminated {
+                    return Err(RelaxError::Lex("unterminated comment, expected '*/'".to_string()).at(Span { end: start + 2, ..span }));
+                }
+                continue;
+            },
+            '/' => Token::Divide,
+            '!' => {
+                if let Some((_, '=')) = chars.peek() {
+                    chars.next();
+                    Token::NotEquals
+                } else {
+                    Token::Not
+                }
+            },
+            '&' | '|' => {
+                // `&&` and `||` are the only operators spelled with these
+                if chars.next_if( |(_, next)| *next == c).is_none() {
+                    return Err(RelaxError::Lex(format!("unexpected character '{c}', did you mean '{c}{c}'?")).at(span));
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            },
+            '≠' => Token::NotEquals,
+            '≤' => Token::LesserEq,
+            '≥' => Token::GreaterEq,
+            '∧' => Token::And,
+            '∨' => Token::Or,
+            '¬' => Token::Not,
+            // Relational algebra as it is written in RelaX and textbooks
+            'σ' => Token::Select,
+            'π' => Token::Project,
+            'ρ' => Token::Rename,
+            'γ' => Token::Gamma,
+            'τ' => Token::Tau,
+            '⋈' => Token::Join,
+            '⟕' => Token::LeftJoin,
+            '⟖' => Token::RightJoin,
+            '⟗' => Token::FullJoin,
+            '∪' => Token::Union,
+            '∩' => Token::Intersect,
+            '×' => Token::Multiply,
+            '÷' => Token::Divide,
+            '⋉' => Token::LeftSemiJoin,
+            '⋊' => Token::RightSemiJoin,
+            '▷' => Token::AntiJoin,
+            '>' => {
+                if let Some((_, '=')) = chars.peek() {
+                    chars.next();
+                    Token::GreaterEq
+                } else {
+                    Token::Greater
+                }
             },
             '<' => {
-                if let Some(next) = chars.peek()  {
-                   if *next == '=' {
+                match chars.peek() {
+                    Some((_, '=')) => {
                         chars.next();
-                        tokens.push(Token::LesserEq);
-                        continue;
-                   }
-                } 
-                tokens.push(Token::Lesser);
-            },
-            '"' => {
-                let mut word: String = "".to_string();
-
-                while let Some(c) = chars.next() {
-                    if c == '"' {break};
-                    word.push(c);
+                        Token::LesserEq
+                    },
+                    Some((_, '>')) => {
+                        chars.next();
+                        Token::NotEquals
+                    },
+                    _ => Token::Lesser,
                 }
-                // TODO: Add error checking for unterminated strings
-
-                tokens.push(Token::String(word));
             },
-            '\'' => {
+            '"' | '\'' => {
                 let mut word: String = "".to_string();
+                let mut terminated = false;
 
-                while let Some(c) = chars.next() {
-                    if c == '\'' {break};
-                    word.push(c);
+                for (i, next) in chars.by_ref() {
+                    if next == c {
+                        terminated = true;
+                        break;
+                    };
+                    // Keep line numbers right for anything after the string
+                    if next == '\n' {
+                        line += 1;
+                        line_start = i + 1;
+                    }
+                    word.push(next);
+                }
+                if !terminated {
+                    return Err(RelaxError::Lex(format!("unterminated string {c}{}", word.trim_end())).at(span));
                 }
-                // TODO: Add error checking for unterminated strings
 
-                tokens.push(Token::String(word));
+                Token::String(word)
             },
-            _ if c.is_whitespace() => (),
+            '\n' => {
+                line += 1;
+                line_start = start + 1;
+                continue;
+            },
+            _ if c.is_whitespace() => continue,
             c if c.is_alphabetic() => {
                 let mut word: String = "".to_string();
                 word.push(c);
 
-                while let Some(c) = chars.peek() {
-                    if !c.is_alphanumeric() {break};
-                    word.push(chars.next().unwrap());
+                while let Some(&(i, c)) = chars.peek() {
+                    // A '.' followed by a letter continues a qualified name like `R.a`
+                    let qualifier = c == '.' && input[i + 1..].starts_with(char::is_alphabetic);
+                    if !c.is_alphanumeric() && !qualifier {break};
+                    word.push(chars.next().unwrap().1);
                 }
 
                 match word.as_str() {
-                    "project" => tokens.push(Token::Project),
-                    "select" => tokens.push(Token::Select),
-                    "join" => tokens.push(Token::Join),
-                    "rightJoin" => tokens.push(Token::RightJoin),
-                    "leftJoin" => tokens.push(Token::LeftJoin),
-                    "fullJoin" => tokens.push(Token::FullJoin),
-                    "intersect" => tokens.push(Token::Intersect),
-                    "union" => tokens.push(Token::Union),
-                    "and" => tokens.push(Token::And),
-                    "or" => tokens.push(Token::Or),
-                    "not" => tokens.push(Token::Not),
-                    _ => tokens.push(Token::Symbol(word)),
+                    "project" => Token::Project,
+                    "select" => Token::Select,
+                    "rename" => Token::Rename,
+                    "gamma" => Token::Gamma,
+                    "tau" => Token::Tau,
+                    "order" => {
+                        // `order by` is an alias for tau
+                        let rest = &input[start + word.len()..];
+                        let by = rest.trim_start_matches([' ', '\t']);
+                        if !by.starts_with("by") || by[2..].starts_with(char::is_alphanumeric) {
+                            Token::Symbol(word)
+                        } else {
+                            let end = start + word.len() + (rest.len() - by.len()) + 2;
+                            while chars.next_if( |(i, _)| *i < end).is_some() {}
+                            Token::Tau
+                        }
+                    },
+                    "join" => Token::Join,
+                    "rightJoin" => Token::RightJoin,
+                    "leftJoin" => Token::LeftJoin,
+                    "fullJoin" => Token::FullJoin,
+                    "naturalJoin" => Token::NaturalJoin,
+                    "leftSemiJoin" => Token::LeftSemiJoin,
+                    "rightSemiJoin" => Token::RightSemiJoin,
+                    "antiJoin" => Token::AntiJoin,
+                    "intersect" => Token::Intersect,
+                    "union" => Token::Union,
+                    // Condition keywords are often written in upper case
+                    _ => match word.to_lowercase().as_str() {
+                        "and" => Token::And,
+                        "or" => Token::Or,
+                        "not" => Token::Not,
+                        "is" => Token::Is,
+                        "like" => Token::Like,
+                        "in" => Token::In,
+                        "between" => Token::Between,
+                        "case" => Token::Case,
+                        "when" => Token::When,
+                        "then" => Token::Then,
+                        "else" => Token::Else,
+                        "end" => Token::End,
+                        "null" => Token::Null,
+                        "true" => Token::Boolean(true),
+                        "false" => Token::Boolean(false),
+                        _ => Token::Symbol(word),
+                    },
                 }
             },
-            c if c.is_numeric() => {
+            c if c.is_ascii_digit() => {
                 let mut word: String = "".to_string();
                 word.push(c);
 
-                while let Some(c) = chars.peek() {
-                    if !c.is_numeric() {break};
-                    word.push(chars.next().unwrap());
+                push_digits(&mut chars, &mut word);
+
+                // A fraction needs a digit after the '.'
+                if let Some(&(i, '.')) = chars.peek() {
+                    if input[i + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                        word.push(chars.next().unwrap().1);
+                        push_digits(&mut chars, &mut word);
+                    }
+                }
+
+                // So does an exponent, after an optional sign
+                if let Some(&(i, e @ ('e' | 'E'))) = chars.peek() {
+                    let rest = &input[i + 1..];
+                    let sign = rest.starts_with(['+', '-']) as usize;
+                    if rest[sign..].starts_with(|c: char| c.is_ascii_digit()) {
+                        chars.next();
+                        word.push(e);
+                        if sign == 1 {
+                            word.push(chars.next().unwrap().1);
+                        }
+                        push_digits(&mut chars, &mut word);
+                    }
                 }
 
-                tokens.push(Token::Number(word));
+                Token::Number(word)
             },
-            invalid_char => panic!("Error: unable to parse '{invalid_char}'"),
-        }
+            invalid_char => return Err(RelaxError::Lex(format!("unexpected character '{invalid_char}'")).at(span)),
+        };
+        let end = chars.peek().map_or(input.len(), |(i, _)| *i);
+        tokens.push(SpannedToken { token, span: Span { end, ..span } });
     }
 
-    // Done reading line
-    tokens.push(Token::EOF);
+    // Done reading input
+    let end = input.trim_end().len();
+    tokens.push(SpannedToken {
+        token: Token::Eof,
+        span: Span {
+            start: end,
+            end,
+            line: input[..end].matches('\n').count() + 1,
+            column: input[..end].rsplit('\n').next().unwrap_or("").chars().count() + 1,
+        },
+    });
     
-    tokens
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<Token> {
+        get_tokens(input).unwrap().into_iter().map( |spanned| spanned.token).collect()
+    }
+
+    fn symbol(name: &str) -> Token {
+        Token::Symbol(name.to_string())
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(tokens("1.5e-3 2E+4 3e"), vec![Token::Number("1.5e-3".to_string()), Token::Number("2E+4".to_string()),
+            Token::Number("3".to_string()), symbol("e"), Token::Eof]);
+        // A fraction needs a digit after the '.'
+        assert!(get_tokens("2.").is_err());
+    }
+
+    #[test]
+    fn left_arrow_is_less_than_minus() {
+        assert_eq!(tokens("a<-1"), vec![symbol("a"), Token::Lesser, Token::Minus, Token::Number("1".to_string()), Token::Eof]);
+        assert_eq!(tokens("a<=b<>c->d"), vec![symbol("a"), Token::LesserEq, symbol("b"), Token::NotEquals, symbol("c"), Token::Arrow, symbol("d"), Token::Eof]);
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(tokens("a -- b\nc"), vec![symbol("a"), symbol("c"), Token::Eof]);
+        assert_eq!(tokens("a - -1"), vec![symbol("a"), Token::Minus, Token::Minus, Token::Number("1".to_string()), Token::Eof]);
+        assert_eq!(tokens("a /* b\n* c */ d / e"), vec![symbol("a"), symbol("d"), Token::Divide, symbol("e"), Token::Eof]);
+        assert!(get_tokens("a /* b").is_err());
+    }
+
+    #[test]
+    fn order_by() {
+        assert_eq!(tokens("order by a"), vec![Token::Tau, symbol("a"), Token::Eof]);
+        assert_eq!(tokens("order  by a"), vec![Token::Tau, symbol("a"), Token::Eof]);
+        assert_eq!(tokens("order byte"), vec![symbol("order"), symbol("byte"), Token::Eof]);
+        assert_eq!(tokens("order"), vec![symbol("order"), Token::Eof]);
+    }
+
+    #[test]
+    fn relational_algebra_symbols() {
+        assert_eq!(tokens("σ π ρ γ τ"), vec![Token::Select, Token::Project, Token::Rename, Token::Gamma, Token::Tau, Token::Eof]);
+        assert_eq!(tokens("⋈ ⟕ ∪ ∩ × ÷"), vec![Token::Join, Token::LeftJoin, Token::Union, Token::Intersect, Token::Multiply, Token::Divide, Token::Eof]);
+    }
+
+    #[test]
+    fn lines_after_a_multiline_string() {
+        let spanned = get_tokens("'x\ny'\nR").unwrap();
+        assert_eq!(spanned[0].span.line, 1);
+        assert_eq!(spanned[1].token, Token::Symbol("R".to_string()));
+        assert_eq!((spanned[1].span.line, spanned[1].span.column), (3, 1));
+    }
 }
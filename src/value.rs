@@ -0,0 +1,662 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter, Error};
+use std::str::FromStr;
+use crate::tokenizer::Token;
+use crate::error::RelaxError;
+
+/// The type of a table column.
+#[derive (Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Integer,
+    Decimal,
+    String,
+    Boolean,
+    Date,
+    Timestamp,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let name = match self {
+            Type::Integer => "Integer",
+            Type::Decimal => "Decimal",
+            Type::String => "String",
+            Type::Boolean => "Boolean",
+            Type::Date => "Date",
+            Type::Timestamp => "Timestamp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Type {
+    type Err = RelaxError;
+
+    /// Parses a type name such as `Integer`, ignoring case.
+    fn from_str(name: &str) -> Result<Type, RelaxError> {
+        [Type::Integer, Type::Decimal, Type::String, Type::Boolean, Type::Date, Type::Timestamp].into_iter()
+            .find( |ty| ty.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else( || RelaxError::Type(format!("unknown type '{name}'")))
+    }
+}
+
+/// A single typed table cell.
+#[derive (Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    Integer(i64),
+    Decimal(Decimal),
+    String(String),
+    Boolean(bool),
+    Date(Date),
+    Timestamp(Timestamp),
+    Null,
+}
+
+impl Value {
+    /// Parses `text` as a value of type `ty`.
+    pub fn parse(ty: Type, text: &str) -> Result<Value, RelaxError> {
+        match ty {
+            Type::Integer => text.parse().map(Value::Integer).map_err(|_| RelaxError::Type(format!("'{text}' is not a valid Integer"))),
+            Type::Decimal => text.parse().map(Value::Decimal),
+            Type::String => Ok(Value::String(text.to_string())),
+            Type::Boolean => text.parse().map(Value::Boolean).map_err(|_| RelaxError::Type(format!("'{text}' is not a valid Boolean"))),
+            Type::Date => text.parse().map(Value::Date),
+            Type::Timestamp => text.parse().map(Value::Timestamp),
+        }
+    }
+
+    /// Converts a literal token into a value.
+    pub fn from_token(token: &Token) -> Result<Value, RelaxError> {
+        match token {
+            // Whole numbers too large for an Integer are kept exactly as Decimals
+            Token::Number(text) if text.contains(['.', 'e', 'E']) || text.parse::<i64>().is_err() => Value::parse(Type::Decimal, text),
+            Token::Number(text) => Value::parse(Type::Integer, text),
+            Token::String(text) => Value::parse(Type::String, text),
+            Token::Boolean(value) => Ok(Value::Boolean(*value)),
+            Token::Null => Ok(Value::Null),
+            _ => Err(RelaxError::Parse(format!("expected a value but found {token:?}"))),
+        }
+    }
+
+    /// Compares two values of the same type. Integers are promoted when
+    /// compared against Decimals, and Dates are midnight when compared
+    /// against Timestamps; any other mix of types is an error.
+    /// Comparisons involving `Null` have no ordering.
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>, RelaxError> {
+        let ordering = match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Integer(left), Value::Integer(right)) => Some(left.cmp(right)),
+            (Value::Integer(left), Value::Decimal(right)) => Some(Decimal::from(*left).cmp(right)),
+            (Value::Decimal(left), Value::Integer(right)) => Some(left.cmp(&Decimal::from(*right))),
+            (Value::Decimal(left), Value::Decimal(right)) => Some(left.cmp(right)),
+            (Value::String(left), Value::String(right)) => Some(left.cmp(right)),
+            (Value::Boolean(left), Value::Boolean(right)) => Some(left.cmp(right)),
+            (Value::Date(left), Value::Date(right)) => Some(left.cmp(right)),
+            (Value::Timestamp(left), Value::Timestamp(right)) => Some(left.cmp(right)),
+            (Value::Date(left), Value::Timestamp(right)) => Some(Timestamp::from(*left).cmp(right)),
+            (Value::Timestamp(left), Value::Date(right)) => Some(left.cmp(&Timestamp::from(*right))),
+            (left, right) => {
+                return Err(RelaxError::Type(format!("cannot compare {} value '{left}' with {} value '{right}'",
+                    left.ty().unwrap(), right.ty().unwrap())));
+            },
+        };
+        Ok(ordering)
+    }
+
+    /// Applies a numeric operator, promoting Integers to Decimals when mixed
+    /// or when there is no `integer` version of the operator.
+    /// Any `Null` operand gives `Null`.
+    fn arithmetic(&self, other: &Value, symbol: &str,
+        integer: Option<fn(i64, i64) -> Option<i64>>,
+        decimal: fn(&Decimal, &Decimal) -> Option<Decimal>) -> Result<Value, RelaxError> {
+        let overflow = || RelaxError::Arithmetic(format!("{self} {symbol} {other} overflows"));
+        match (self, other, integer) {
+            (Value::Null, _, _) | (_, Value::Null, _) => Ok(Value::Null),
+            (Value::Integer(left), Value::Integer(right), Some(integer)) => integer(*left, *right).map(Value::Integer).ok_or_else(overflow),
+            (left, right, _) => match (left.to_decimal(), right.to_decimal()) {
+                (Some(left), Some(right)) => decimal(&left, &right).map(Value::Decimal).ok_or_else(overflow),
+                _ => Err(RelaxError::Type(format!("cannot apply '{symbol}' to {} value '{left}' and {} value '{right}'",
+                    left.ty().unwrap(), right.ty().unwrap()))),
+            },
+        }
+    }
+
+    /// Adds two numbers, or a number of days to a Date or Timestamp.
+    pub fn add(&self, other: &Value) -> Result<Value, RelaxError> {
+        match (self, other) {
+            (Value::Date(date), Value::Integer(days)) | (Value::Integer(days), Value::Date(date)) => date.checked_add_days(*days)
+                .map(Value::Date)
+                .ok_or_else( || RelaxError::Arithmetic(format!("{self} + {other} is out of range"))),
+            (Value::Timestamp(timestamp), Value::Integer(days)) | (Value::Integer(days), Value::Timestamp(timestamp)) => timestamp.checked_add_days(*days)
+                .map(Value::Timestamp)
+                .ok_or_else( || RelaxError::Arithmetic(format!("{self} + {other} is out of range"))),
+            _ => self.arithmetic(other, "+", Some(i64::checked_add), Decimal::checked_add),
+        }
+    }
+
+    /// Subtracts two numbers, a number of days from a Date or Timestamp, or
+    /// two Dates to get the days between them.
+    pub fn sub(&self, other: &Value) -> Result<Value, RelaxError> {
+        match (self, other) {
+            (Value::Date(date), Value::Integer(days)) => days.checked_neg()
+                .and_then( |days| date.checked_add_days(days))
+                .map(Value::Date)
+                .ok_or_else( || RelaxError::Arithmetic(format!("{self} - {other} is out of range"))),
+            (Value::Timestamp(timestamp), Value::Integer(days)) => days.checked_neg()
+                .and_then( |days| timestamp.checked_add_days(days))
+                .map(Value::Timestamp)
+                .ok_or_else( || RelaxError::Arithmetic(format!("{self} - {other} is out of range"))),
+            (Value::Date(left), Value::Date(right)) => Ok(Value::Integer(left.days - right.days)),
+            _ => self.arithmetic(other, "-", Some(i64::checked_sub), Decimal::checked_sub),
+        }
+    }
+
+    pub fn mul(&self, other: &Value) -> Result<Value, RelaxError> {
+        self.arithmetic(other, "*", Some(i64::checked_mul), Decimal::checked_mul)
+    }
+
+    /// Divides two numbers. The result is always a Decimal, so `7 / 2` is `3.5`.
+    pub fn div(&self, other: &Value) -> Result<Value, RelaxError> {
+        if other.to_decimal() == Some(Decimal::from(0)) {
+            return Err(RelaxError::Arithmetic(format!("division by zero in {self} / {other}")));
+        }
+        self.arithmetic(other, "/", None, Decimal::checked_div)
+    }
+
+    pub fn neg(&self) -> Result<Value, RelaxError> {
+        match self {
+            Value::Null => Ok(Value::Null),
+            Value::Integer(value) => value.checked_neg().map(Value::Integer)
+                .ok_or_else( || RelaxError::Arithmetic(format!("-{value} overflows"))),
+            Value::Decimal(value) => Ok(Value::Decimal(Decimal::new(-value.mantissa, value.scale))),
+            value => Err(RelaxError::Type(format!("cannot negate {} value '{value}'", value.ty().unwrap()))),
+        }
+    }
+
+    /// This value as a Decimal, if it is a number.
+    pub fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Value::Integer(value) => Some(Decimal::from(*value)),
+            Value::Decimal(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The type of this value, or `None` for `Null`.
+    pub fn ty(&self) -> Option<Type> {
+        match self {
+            Value::Integer(_) => Some(Type::Integer),
+            Value::Decimal(_) => Some(Type::Decimal),
+            Value::String(_) => Some(Type::String),
+            Value::Boolean(_) => Some(Type::Boolean),
+            Value::Date(_) => Some(Type::Date),
+            Value::Timestamp(_) => Some(Type::Timestamp),
+            Value::Null => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::Decimal(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::Date(value) => write!(f, "{value}"),
+            Value::Timestamp(value) => write!(f, "{value}"),
+            Value::Null => write!(f, "Null"),
+        }
+    }
+}
+
+const MAX_SCALE: u32 = 28;
+/// Digits kept after the decimal point when a division isn't exact.
+const DIVISION_SCALE: u32 = 10;
+
+/// An exact base-10 number stored as `mantissa * 10^-scale`.
+///
+/// Values are kept normalized (no trailing zeros in the fraction) so that
+/// the derived `PartialEq` and `Hash` agree with numeric equality.
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+}
+
+impl Decimal {
+    pub fn new(mantissa: i128, scale: u32) -> Decimal {
+        if scale > MAX_SCALE {
+            // Round to the finest scale we can compare
+            return Decimal { mantissa, scale }.round(MAX_SCALE);
+        }
+        let mut result = Decimal { mantissa, scale };
+        while result.scale > 0 && result.mantissa % 10 == 0 {
+            result.mantissa /= 10;
+            result.scale -= 1;
+        }
+        result
+    }
+
+    /// Rounds half away from zero to `scale` digits after the decimal point.
+    pub fn round(&self, scale: u32) -> Decimal {
+        if self.scale <= scale {
+            return *self;
+        }
+        let Some(divisor) = 10i128.checked_pow(self.scale - scale) else {
+            // Too small to be distinguished from zero
+            return Decimal { mantissa: 0, scale: 0 };
+        };
+        let remainder = self.mantissa % divisor;
+        let mut mantissa = self.mantissa / divisor;
+        if remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+            mantissa += remainder.signum();
+        }
+        Decimal::new(mantissa, scale)
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal { mantissa: self.mantissa.abs(), scale: self.scale }
+    }
+
+    /// Rounds to a whole number, if it fits in an Integer.
+    pub fn to_integer(self) -> Option<i64> {
+        i64::try_from(self.round(0).mantissa).ok()
+    }
+
+    /// The mantissas of `self` and `other` scaled to a common scale.
+    fn aligned(&self, other: &Decimal) -> Option<(i128, i128, u32)> {
+        let scale = self.scale.max(other.scale);
+        let left = self.mantissa.checked_mul(10i128.pow(scale - self.scale))?;
+        let right = other.mantissa.checked_mul(10i128.pow(scale - other.scale))?;
+        Some((left, right, scale))
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = self.aligned(other)?;
+        Some(Decimal::new(left.checked_add(right)?, scale))
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let (left, right, scale) = self.aligned(other)?;
+        Some(Decimal::new(left.checked_sub(right)?, scale))
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        Some(Decimal::new(self.mantissa.checked_mul(other.mantissa)?, self.scale + other.scale))
+    }
+
+    /// Divides, rounding half away from zero to `DIVISION_SCALE` digits.
+    /// Returns `None` on division by zero or overflow.
+    pub fn checked_div(&self, other: &Decimal) -> Option<Decimal> {
+        if other.mantissa == 0 {
+            return None;
+        }
+        // (m1 / 10^s1) / (m2 / 10^s2) = (m1 * 10^(s2 + D)) / (m2 * 10^s1) / 10^D
+        let numerator = self.mantissa.checked_mul(10i128.checked_pow(other.scale + DIVISION_SCALE)?)?;
+        let denominator = other.mantissa.checked_mul(10i128.pow(self.scale))?;
+        let mut quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        if remainder.unsigned_abs() * 2 >= denominator.unsigned_abs() {
+            quotient += if (numerator < 0) == (denominator < 0) { 1 } else { -1 };
+        }
+        Some(Decimal::new(quotient, DIVISION_SCALE))
+    }
+
+    fn split(&self) -> (i128, i128) {
+        let divisor = 10i128.pow(self.scale);
+        (self.mantissa / divisor, self.mantissa % divisor)
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Decimal {
+        Decimal::new(value as i128, 0)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = RelaxError;
+
+    fn from_str(text: &str) -> Result<Decimal, RelaxError> {
+        let invalid = || RelaxError::Type(format!("'{text}' is not a valid Decimal"));
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text.strip_prefix('+').unwrap_or(text)),
+        };
+        let (digits, exponent) = match digits.split_once(['e', 'E']) {
+            Some((digits, exponent)) => (digits, exponent.parse::<i32>().map_err(|_| invalid())?),
+            None => (digits, 0),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty() && fraction.is_empty()
+            || !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+            || fraction.len() > MAX_SCALE as usize {
+            return Err(invalid());
+        }
+
+        let mut mantissa: i128 = 0;
+        for c in whole.chars().chain(fraction.chars()) {
+            mantissa = mantissa.checked_mul(10)
+                .and_then(|m| m.checked_add(c.to_digit(10).unwrap() as i128))
+                .ok_or_else(invalid)?;
+        }
+        if negative {
+            mantissa = -mantissa;
+        }
+
+        // A positive exponent scales the mantissa up, a negative one adds fractional digits
+        let scale = fraction.len() as i64 - exponent as i64;
+        if scale < 0 {
+            let factor = u32::try_from(-scale).ok().and_then(|power| 10i128.checked_pow(power));
+            mantissa = factor.and_then(|factor| mantissa.checked_mul(factor)).ok_or_else(invalid)?;
+        }
+
+        Ok(Decimal::new(mantissa, u32::try_from(scale.max(0)).map_err(|_| invalid())?))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let (self_whole, self_fraction) = self.split();
+        let (other_whole, other_fraction) = other.split();
+        let self_fraction = self_fraction * 10i128.pow(MAX_SCALE - self.scale);
+        let other_fraction = other_fraction * 10i128.pow(MAX_SCALE - other.scale);
+        self_whole.cmp(&other_whole).then(self_fraction.cmp(&other_fraction))
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let (whole, fraction) = self.split();
+        let sign = if self.mantissa < 0 && whole == 0 { "-" } else { "" };
+        if self.scale == 0 {
+            write!(f, "{whole}")
+        } else {
+            write!(f, "{sign}{whole}.{:0width$}", fraction.abs(), width = self.scale as usize)
+        }
+    }
+}
+
+/// A calendar date stored as the number of days since 1970-01-01.
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Date {
+    days: i64,
+}
+
+impl Date {
+    pub fn from_ymd(year: i64, month: u32, day: u32) -> Option<Date> {
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        // Howard Hinnant's days_from_civil
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let month = month as i64;
+        let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        Some(Date { days: era * 146097 + day_of_era - 719468 })
+    }
+
+    /// The date `days` days later, if it still has a four digit year.
+    pub fn checked_add_days(&self, days: i64) -> Option<Date> {
+        let result = Date { days: self.days.checked_add(days)? };
+        let first = Date::from_ymd(0, 1, 1)?;
+        let last = Date::from_ymd(9999, 12, 31)?;
+        (first..=last).contains(&result).then_some(result)
+    }
+
+    pub fn ymd(&self) -> (i64, u32, u32) {
+        // Howard Hinnant's civil_from_days
+        let days = self.days + 719468;
+        let era = days.div_euclid(146097);
+        let day_of_era = days.rem_euclid(146097);
+        let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_part = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * month_part + 2) / 5 + 1) as u32;
+        let month = if month_part < 10 { month_part + 3 } else { month_part - 9 } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year, month, day)
+    }
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl FromStr for Date {
+    type Err = RelaxError;
+
+    fn from_str(text: &str) -> Result<Date, RelaxError> {
+        let invalid = || RelaxError::Type(format!("'{text}' is not a valid Date (expected YYYY-MM-DD)"));
+        let mut parts = text.splitn(3, '-');
+        let (Some(year), Some(month), Some(day)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        let digits = |part: &str, length: usize| part.len() == length && part.chars().all( |c| c.is_ascii_digit());
+        if !digits(year, 4) || !digits(month, 2) || !digits(day, 2) {
+            return Err(invalid());
+        }
+        let year = year.parse().map_err(|_| invalid())?;
+        let month = month.parse().map_err(|_| invalid())?;
+        let day = day.parse().map_err(|_| invalid())?;
+        Date::from_ymd(year, month, day).ok_or_else(invalid)
+    }
+}
+
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let (year, month, day) = self.ymd();
+        write!(f, "{year:04}-{month:02}-{day:02}")
+    }
+}
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// A date and time of day to the second, without a time zone, stored as
+/// the number of seconds since 1970-01-01 00:00:00.
+#[derive (Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Timestamp {
+    seconds: i64,
+}
+
+impl Timestamp {
+    pub fn new(date: Date, hour: u32, minute: u32, second: u32) -> Option<Timestamp> {
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        let time = (hour * 60 * 60 + minute * 60 + second) as i64;
+        Some(Timestamp { seconds: date.days * SECONDS_PER_DAY + time })
+    }
+
+    pub fn date(&self) -> Date {
+        Date { days: self.seconds.div_euclid(SECONDS_PER_DAY) }
+    }
+
+    /// The hour, minute and second of the day.
+    pub fn hms(&self) -> (u32, u32, u32) {
+        let time = self.seconds.rem_euclid(SECONDS_PER_DAY) as u32;
+        (time / 3600, time / 60 % 60, time % 60)
+    }
+
+    /// The same time of day `days` days later, if its date is still in range.
+    pub fn checked_add_days(&self, days: i64) -> Option<Timestamp> {
+        let (hour, minute, second) = self.hms();
+        Timestamp::new(self.date().checked_add_days(days)?, hour, minute, second)
+    }
+}
+
+impl From<Date> for Timestamp {
+    /// Midnight at the start of `date`.
+    fn from(date: Date) -> Timestamp {
+        Timestamp { seconds: date.days * SECONDS_PER_DAY }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = RelaxError;
+
+    /// Parses `YYYY-MM-DD HH:MM:SS`, with a `T` or a space between the date
+    /// and the time. The seconds can be left out.
+    fn from_str(text: &str) -> Result<Timestamp, RelaxError> {
+        let invalid = || RelaxError::Type(format!("'{text}' is not a valid Timestamp (expected YYYY-MM-DD HH:MM:SS)"));
+        let (date, time) = text.split_once([' ', 'T']).ok_or_else(invalid)?;
+        let date: Date = date.parse().map_err(|_| invalid())?;
+        let parts = time.split(':')
+            .map( |part| match part.len() == 2 && part.chars().all( |c| c.is_ascii_digit()) {
+                true => part.parse::<u32>().map_err(|_| invalid()),
+                false => Err(invalid()),
+            })
+            .collect::<Result<Vec<u32>, RelaxError>>()?;
+        match parts[..] {
+            [hour, minute] => Timestamp::new(date, hour, minute, 0),
+            [hour, minute, second] => Timestamp::new(date, hour, minute, second),
+            _ => None,
+        }.ok_or_else(invalid)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        let (hour, minute, second) = self.hms();
+        write!(f, "{} {hour:02}:{minute:02}:{second:02}", self.date())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn decimal_round_trips() {
+        for text in ["0", "1", "-1", "1.5", "-0.25", "123.456", "0.0000000001", "-0.5", "99999999999999999999"] {
+            assert_eq!(decimal(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn decimal_parses() {
+        assert_eq!(decimal("1.50"), decimal("1.5"));
+        assert_eq!(decimal("+2.0").to_string(), "2");
+        assert_eq!(decimal(".5").to_string(), "0.5");
+        assert_eq!(decimal("5.").to_string(), "5");
+        assert_eq!(decimal("1.5e3").to_string(), "1500");
+        assert_eq!(decimal("15E-3").to_string(), "0.015");
+        assert_eq!(decimal("-2.5e-1").to_string(), "-0.25");
+        for text in ["", ".", "-", "1.2.3", "1e", "e5", "abc", "1,5", "--1"] {
+            assert!(text.parse::<Decimal>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn decimal_normalizes() {
+        assert_eq!(Decimal::new(1500, 3), Decimal::from(15).checked_div(&Decimal::from(10)).unwrap());
+        assert_eq!(Decimal::new(100, 2), Decimal::from(1));
+        assert_eq!(Decimal::new(0, 5).to_string(), "0");
+        assert_eq!(decimal("0.1").checked_add(&decimal("0.2")), Some(decimal("0.3")));
+        assert_eq!(decimal("1.5").checked_mul(&decimal("2")), Some(decimal("3")));
+    }
+
+    #[test]
+    fn decimal_compares() {
+        assert!(decimal("1.5") > decimal("1.25"));
+        assert!(decimal("-1.5") < decimal("-1.25"));
+        assert!(decimal("-0.5") < decimal("0"));
+        assert!(decimal("10") > decimal("9.999"));
+        assert_eq!(decimal("2.50").cmp(&decimal("2.5")), Ordering::Equal);
+    }
+
+    #[test]
+    fn decimal_division_rounds() {
+        assert_eq!(Decimal::from(1).checked_div(&Decimal::from(3)), Some(decimal("0.3333333333")));
+        assert_eq!(Decimal::from(2).checked_div(&Decimal::from(3)), Some(decimal("0.6666666667")));
+        assert_eq!(Decimal::from(-2).checked_div(&Decimal::from(3)), Some(decimal("-0.6666666667")));
+        assert_eq!(decimal("1.5").checked_div(&decimal("0.5")), Some(Decimal::from(3)));
+        assert_eq!(Decimal::from(1).checked_div(&Decimal::from(0)), None);
+    }
+
+    #[test]
+    fn decimal_rounds() {
+        assert_eq!(decimal("2.5").round(0), Decimal::from(3));
+        assert_eq!(decimal("-2.5").round(0), Decimal::from(-3));
+        assert_eq!(decimal("1.245").round(2), decimal("1.25"));
+        assert_eq!(decimal("1.2").round(3), decimal("1.2"));
+        assert_eq!(decimal("2.5").to_integer(), Some(3));
+    }
+
+    #[test]
+    fn date_round_trips() {
+        for text in ["1970-01-01", "2024-02-29", "2000-02-29", "1969-12-31", "0000-01-01", "9999-12-31", "1600-03-01"] {
+            let date: Date = text.parse().unwrap();
+            assert_eq!(date.to_string(), text);
+            let (year, month, day) = date.ymd();
+            assert_eq!(Date::from_ymd(year, month, day), Some(date));
+        }
+    }
+
+    #[test]
+    fn date_checks_the_calendar() {
+        assert!(Date::from_ymd(2023, 2, 29).is_none());
+        assert!(Date::from_ymd(1900, 2, 29).is_none());
+        assert!(Date::from_ymd(2000, 2, 29).is_some());
+        assert!(Date::from_ymd(2024, 4, 31).is_none());
+        assert!(Date::from_ymd(2024, 13, 1).is_none());
+        assert!(Date::from_ymd(2024, 1, 0).is_none());
+        for text in ["2024-1-01", "24-01-01", "2024/01/01", "2024-01-01x", "+999-01-01", "2024-+1-01", "2024-01"] {
+            assert!(text.parse::<Date>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn date_arithmetic_stays_in_range() {
+        let date: Date = "2024-02-28".parse().unwrap();
+        assert_eq!(date.checked_add_days(1).unwrap().to_string(), "2024-02-29");
+        assert_eq!(date.checked_add_days(2).unwrap().to_string(), "2024-03-01");
+        assert_eq!(date.checked_add_days(-59).unwrap().to_string(), "2023-12-31");
+        let last: Date = "9999-12-31".parse().unwrap();
+        assert!(last.checked_add_days(1).is_none());
+        let first: Date = "0000-01-01".parse().unwrap();
+        assert!(first.checked_add_days(-1).is_none());
+        assert!(first < last);
+    }
+
+    #[test]
+    fn timestamp_round_trips() {
+        for text in ["1970-01-01 00:00:00", "1969-12-31 23:59:59", "2024-02-29 12:34:56", "0000-01-01 00:00:00", "9999-12-31 23:59:59"] {
+            let timestamp: Timestamp = text.parse().unwrap();
+            assert_eq!(timestamp.to_string(), text);
+        }
+        assert_eq!("2024-01-24T08:30".parse::<Timestamp>().unwrap().to_string(), "2024-01-24 08:30:00");
+        for text in ["2024-01-24", "2024-01-24 24:00", "2024-01-24 12:60", "2024-01-24 1:00", "2024-01-24 12:00:00:00", "2024-02-30 12:00"] {
+            assert!(text.parse::<Timestamp>().is_err(), "{text}");
+        }
+    }
+
+    #[test]
+    fn timestamps_compare_with_dates() {
+        let date = Value::Date("2024-01-24".parse().unwrap());
+        let midnight = Value::Timestamp("2024-01-24 00:00".parse().unwrap());
+        let noon = Value::Timestamp("2024-01-24 12:00".parse().unwrap());
+        assert_eq!(date.compare(&midnight).unwrap(), Some(Ordering::Equal));
+        assert_eq!(noon.compare(&date).unwrap(), Some(Ordering::Greater));
+        assert_eq!(noon.add(&Value::Integer(8)).unwrap().to_string(), "2024-02-01 12:00:00");
+        assert_eq!(noon.sub(&Value::Integer(24)).unwrap().to_string(), "2023-12-31 12:00:00");
+    }
+}